@@ -0,0 +1,473 @@
+use crate::Value;
+
+/// The parts of a wrap's ABI, from `wrap.info`, needed to check and convert invocation values.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Abi {
+    pub object_types: Vec<ObjectDefinition>,
+    pub enum_types: Vec<EnumDefinition>,
+    pub methods: Vec<MethodDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectDefinition {
+    pub name: String,
+    pub properties: Vec<PropertyDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumDefinition {
+    pub name: String,
+    /// Constants are encoded as their index
+    pub constants: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodDefinition {
+    pub name: String,
+    pub arguments: Vec<PropertyDefinition>,
+    pub return_type: Option<TypeDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PropertyDefinition {
+    pub name: String,
+    pub ty: TypeDefinition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeDefinition {
+    pub kind: TypeKind,
+    /// Optional values may be nil or omitted
+    pub required: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeKind {
+    /// `UInt`, `Int8`, `String`, `Boolean`, `Bytes`, `BigInt`, `BigNumber`, `JSON`...
+    Scalar(String),
+    Object(String),
+    Enum(String),
+    Array(Box<TypeDefinition>),
+    /// Encoded as a map inside msgpack extension type 1
+    Map(Box<TypeDefinition>, Box<TypeDefinition>),
+}
+
+impl TypeKind {
+    /// The type as written in a schema, e.g. `[String]` or `Map<String, Int>`.
+    pub fn name(&self) -> String {
+        match self {
+            TypeKind::Scalar(name) | TypeKind::Object(name) | TypeKind::Enum(name) => name.clone(),
+            TypeKind::Array(item) => format!("[{}]", item.name()),
+            TypeKind::Map(key, value) => format!("Map<{}, {}>", key.name(), value.name()),
+        }
+    }
+}
+
+impl TypeDefinition {
+    /// The type as written in a schema, with `!` if it's required.
+    pub fn name(&self) -> String {
+        match self.required {
+            true => format!("{}!", self.kind.name()),
+            false => self.kind.name(),
+        }
+    }
+}
+
+impl Abi {
+    /// Parses the `abi` of a msgpack encoded `wrap.info`. Returns `None` if the manifest isn't understood.
+    pub fn from_manifest(manifest: &[u8]) -> Option<Self> {
+        let manifest = Value::decode(manifest).ok()?;
+        let abi = manifest.get("abi")?;
+
+        let mut object_types = vec![];
+        for key in ["objectTypes", "importedObjectTypes"] {
+            for object in array(abi.get(key)) {
+                object_types.push(ObjectDefinition {
+                    name: object.get("type")?.as_str()?.to_string(),
+                    properties: parse_properties(object.get("properties"))?,
+                });
+            }
+        }
+
+        let mut enum_types = vec![];
+        for key in ["enumTypes", "importedEnumTypes"] {
+            for enum_type in array(abi.get(key)) {
+                enum_types.push(EnumDefinition {
+                    name: enum_type.get("type")?.as_str()?.to_string(),
+                    constants: array(enum_type.get("constants"))
+                        .iter()
+                        .map(|constant| constant.as_str().map(str::to_string))
+                        .collect::<Option<_>>()?,
+                });
+            }
+        }
+
+        let mut methods = vec![];
+        if let Some(module_type) = abi.get("moduleType") {
+            for method in array(module_type.get("methods")) {
+                methods.push(MethodDefinition {
+                    name: method.get("name")?.as_str()?.to_string(),
+                    arguments: parse_properties(method.get("arguments"))?,
+                    return_type: match method.get("return") {
+                        Some(return_type) => Some(parse_type(return_type)?),
+                        None => None,
+                    },
+                });
+            }
+        }
+
+        let mut abi = Self {
+            object_types,
+            enum_types,
+            methods,
+        };
+        abi.resolve_object_or_enum_types();
+        Some(abi)
+    }
+
+    pub fn method(&self, name: &str) -> Option<&MethodDefinition> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    pub fn object_type(&self, name: &str) -> Option<&ObjectDefinition> {
+        self.object_types.iter().find(|object| object.name == name)
+    }
+
+    pub fn enum_type(&self, name: &str) -> Option<&EnumDefinition> {
+        self.enum_types
+            .iter()
+            .find(|enum_type| enum_type.name == name)
+    }
+
+    /// Encodes the ABI as a msgpack `wrap.info`, which [`Abi::from_manifest`] parses back.
+    pub fn to_manifest(&self, name: &str) -> Vec<u8> {
+        let object_types = self
+            .object_types
+            .iter()
+            .map(|object| {
+                map([
+                    ("type", string(&object.name)),
+                    ("properties", encode_properties(&object.properties)),
+                ])
+            })
+            .collect();
+        let enum_types = self
+            .enum_types
+            .iter()
+            .map(|enum_type| {
+                map([
+                    ("type", string(&enum_type.name)),
+                    (
+                        "constants",
+                        Value::Array(enum_type.constants.iter().map(|c| string(c)).collect()),
+                    ),
+                ])
+            })
+            .collect();
+        let methods = self
+            .methods
+            .iter()
+            .map(|method| {
+                let mut entries = vec![
+                    ("name", string(&method.name)),
+                    ("type", string("Method")),
+                    ("arguments", encode_properties(&method.arguments)),
+                ];
+                if let Some(return_type) = &method.return_type {
+                    entries.push(("return", encode_property(&method.name, return_type)));
+                }
+                map(entries)
+            })
+            .collect();
+
+        map([
+            ("version", string("0.1")),
+            ("name", string(name)),
+            ("type", string("plugin")),
+            (
+                "abi",
+                map([
+                    ("version", string("0.1")),
+                    ("objectTypes", Value::Array(object_types)),
+                    ("enumTypes", Value::Array(enum_types)),
+                    (
+                        "moduleType",
+                        map([
+                            ("type", string("Module")),
+                            ("methods", Value::Array(methods)),
+                        ]),
+                    ),
+                ]),
+            ),
+        ])
+        .encode()
+    }
+
+    /// Schemas can leave it to the ABI consumer to tell whether a named type is an object or an enum.
+    fn resolve_object_or_enum_types(&mut self) {
+        let enum_names: Vec<String> = self.enum_types.iter().map(|e| e.name.clone()).collect();
+        let resolve = |ty: &mut TypeDefinition| resolve_type(ty, &enum_names);
+
+        for object in &mut self.object_types {
+            object
+                .properties
+                .iter_mut()
+                .for_each(|p| resolve(&mut p.ty));
+        }
+        for method in &mut self.methods {
+            method.arguments.iter_mut().for_each(|p| resolve(&mut p.ty));
+            if let Some(return_type) = &mut method.return_type {
+                resolve(return_type);
+            }
+        }
+    }
+}
+
+fn resolve_type(ty: &mut TypeDefinition, enum_names: &[String]) {
+    match &mut ty.kind {
+        TypeKind::Object(name) if enum_names.contains(name) => {
+            ty.kind = TypeKind::Enum(name.clone());
+        }
+        TypeKind::Array(item) => resolve_type(item, enum_names),
+        TypeKind::Map(key, value) => {
+            resolve_type(key, enum_names);
+            resolve_type(value, enum_names);
+        }
+        _ => {}
+    }
+}
+
+fn array(value: Option<&Value>) -> &[Value] {
+    match value {
+        Some(Value::Array(values)) => values,
+        _ => &[],
+    }
+}
+
+fn parse_properties(properties: Option<&Value>) -> Option<Vec<PropertyDefinition>> {
+    array(properties)
+        .iter()
+        .map(|property| {
+            Some(PropertyDefinition {
+                name: property.get("name")?.as_str()?.to_string(),
+                ty: parse_type(property)?,
+            })
+        })
+        .collect()
+}
+
+fn parse_type(definition: &Value) -> Option<TypeDefinition> {
+    let required = matches!(definition.get("required"), Some(Value::Bool(true)));
+
+    let kind = if let Some(scalar) = definition.get("scalar") {
+        TypeKind::Scalar(scalar.get("type")?.as_str()?.to_string())
+    } else if let Some(array) = definition.get("array") {
+        TypeKind::Array(Box::new(parse_type(array.get("item")?)?))
+    } else if let Some(map) = definition.get("map") {
+        TypeKind::Map(
+            Box::new(parse_type(map.get("key")?)?),
+            Box::new(parse_type(map.get("value")?)?),
+        )
+    } else if let Some(object) = definition.get("object") {
+        TypeKind::Object(object.get("type")?.as_str()?.to_string())
+    } else if let Some(enum_type) = definition.get("enum") {
+        TypeKind::Enum(enum_type.get("type")?.as_str()?.to_string())
+    } else {
+        // Array items and map keys and values only carry the type name
+        return parse_type_name(definition.get("type")?.as_str()?, required);
+    };
+
+    Some(TypeDefinition { kind, required })
+}
+
+/// Parses names like `String`, `[Int]` or `Map<String, [Foo]>`. Named types are assumed to be objects
+/// until enums are resolved. Nested types have no requiredness in their names, so they're optional.
+fn parse_type_name(name: &str, required: bool) -> Option<TypeDefinition> {
+    let name = name.trim();
+    let (name, required) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, required),
+    };
+
+    let kind = if let Some(item) = name.strip_prefix('[').and_then(|n| n.strip_suffix(']')) {
+        TypeKind::Array(Box::new(parse_type_name(item, false)?))
+    } else if let Some(inner) = name.strip_prefix("Map<").and_then(|n| n.strip_suffix('>')) {
+        // Keys are always scalars, so the first comma separates the key from the value
+        let (key, value) = inner.split_once(',')?;
+        TypeKind::Map(
+            Box::new(parse_type_name(key, true)?),
+            Box::new(parse_type_name(value, false)?),
+        )
+    } else if SCALARS.contains(&name) {
+        TypeKind::Scalar(name.to_string())
+    } else if !name.is_empty() {
+        TypeKind::Object(name.to_string())
+    } else {
+        return None;
+    };
+
+    Some(TypeDefinition { kind, required })
+}
+
+fn encode_properties(properties: &[PropertyDefinition]) -> Value {
+    Value::Array(
+        properties
+            .iter()
+            .map(|property| encode_property(&property.name, &property.ty))
+            .collect(),
+    )
+}
+
+fn encode_property(name: &str, ty: &TypeDefinition) -> Value {
+    let mut entries = vec![("name", string(name))];
+    entries.extend(encode_type(ty));
+    map(entries)
+}
+
+/// The entries describing `ty`, in the shape [`parse_type`] reads.
+fn encode_type(ty: &TypeDefinition) -> Vec<(&'static str, Value)> {
+    let header = || {
+        vec![
+            ("type", string(&ty.kind.name())),
+            ("required", Value::Bool(ty.required)),
+        ]
+    };
+    let (key, definition) = match &ty.kind {
+        TypeKind::Scalar(_) => ("scalar", header()),
+        TypeKind::Object(_) => ("object", header()),
+        TypeKind::Enum(_) => ("enum", header()),
+        TypeKind::Array(item) => {
+            let mut definition = header();
+            definition.push(("item", map(encode_type(item))));
+            ("array", definition)
+        }
+        TypeKind::Map(key, value) => {
+            let mut definition = header();
+            definition.push(("key", map(encode_type(key))));
+            definition.push(("value", map(encode_type(value))));
+            ("map", definition)
+        }
+    };
+
+    let mut entries = header();
+    entries.push((key, map(definition)));
+    entries
+}
+
+fn map<'a>(entries: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    Value::Map(
+        entries
+            .into_iter()
+            .map(|(key, value)| (string(key), value))
+            .collect(),
+    )
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+const SCALARS: [&str; 18] = [
+    "UInt",
+    "UInt8",
+    "UInt16",
+    "UInt32",
+    "UInt64",
+    "Int",
+    "Int8",
+    "Int16",
+    "Int32",
+    "Int64",
+    "Float32",
+    "Float64",
+    "String",
+    "Boolean",
+    "Bytes",
+    "BigInt",
+    "BigNumber",
+    "JSON",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ty(kind: TypeKind, required: bool) -> TypeDefinition {
+        TypeDefinition { kind, required }
+    }
+
+    fn scalar(name: &str) -> TypeKind {
+        TypeKind::Scalar(name.to_string())
+    }
+
+    fn property(name: &str, ty: TypeDefinition) -> PropertyDefinition {
+        PropertyDefinition {
+            name: name.to_string(),
+            ty,
+        }
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let node = TypeKind::Object("Node".to_string());
+        let color = TypeKind::Enum("Color".to_string());
+        let abi = Abi {
+            object_types: vec![ObjectDefinition {
+                name: "Node".to_string(),
+                properties: vec![
+                    property("id", ty(scalar("UInt64"), true)),
+                    property("weight", ty(scalar("Float64"), false)),
+                    property("color", ty(color.clone(), false)),
+                    property(
+                        "children",
+                        ty(TypeKind::Array(Box::new(ty(node.clone(), true))), true),
+                    ),
+                    property(
+                        "tags",
+                        ty(
+                            TypeKind::Map(
+                                Box::new(ty(scalar("String"), true)),
+                                Box::new(ty(
+                                    TypeKind::Array(Box::new(ty(scalar("Int"), false))),
+                                    false,
+                                )),
+                            ),
+                            false,
+                        ),
+                    ),
+                ],
+            }],
+            enum_types: vec![EnumDefinition {
+                name: "Color".to_string(),
+                constants: vec!["RED".to_string(), "GREEN".to_string()],
+            }],
+            methods: vec![
+                MethodDefinition {
+                    name: "walk".to_string(),
+                    arguments: vec![
+                        property("root", ty(node.clone(), true)),
+                        property(
+                            "colors",
+                            ty(TypeKind::Array(Box::new(ty(color, true))), false),
+                        ),
+                    ],
+                    return_type: Some(ty(TypeKind::Array(Box::new(ty(node, true))), true)),
+                },
+                MethodDefinition {
+                    name: "reset".to_string(),
+                    arguments: vec![],
+                    return_type: None,
+                },
+            ],
+        };
+
+        let manifest = abi.to_manifest("test-plugin");
+
+        assert_eq!(Abi::from_manifest(&manifest), Some(abi));
+        let manifest = Value::decode(&manifest).unwrap();
+        assert_eq!(
+            manifest.get("name").and_then(Value::as_str),
+            Some("test-plugin")
+        );
+    }
+}
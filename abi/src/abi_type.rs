@@ -0,0 +1,120 @@
+use crate::{Abi, EnumDefinition, ObjectDefinition, PropertyDefinition, TypeDefinition, TypeKind};
+use polywrap_msgpack_serde::{serde_bytes::ByteBuf, BigIntWrapper, BigNumber, JSONString, Map};
+
+/// A Rust type that can describe itself in a wrap's ABI, so plugins can publish a manifest.
+/// Types generated by `wrap_bindings!` implement it.
+pub trait AbiType {
+    /// The type, adding the object and enum types it refers to to `abi`.
+    fn type_definition(abi: &mut Abi) -> TypeDefinition;
+}
+
+/// A struct whose fields are ABI properties. Method args are described by their properties.
+pub trait AbiObject {
+    fn properties(abi: &mut Abi) -> Vec<PropertyDefinition>;
+}
+
+impl Abi {
+    /// Adds `T` as an object type named `name`, unless it's already there.
+    pub fn add_object_type<T: AbiObject>(&mut self, name: &str) -> TypeDefinition {
+        if self.object_type(name).is_none() {
+            // Added before its properties are known, so types referring to themselves terminate
+            self.object_types.push(ObjectDefinition {
+                name: name.to_string(),
+                properties: vec![],
+            });
+            let properties = T::properties(self);
+            if let Some(object) = self.object_types.iter_mut().find(|o| o.name == name) {
+                object.properties = properties;
+            }
+        }
+        TypeDefinition {
+            kind: TypeKind::Object(name.to_string()),
+            required: true,
+        }
+    }
+
+    /// Adds an enum type named `name`, unless it's already there.
+    pub fn add_enum_type(&mut self, name: &str, constants: &[&str]) -> TypeDefinition {
+        if self.enum_type(name).is_none() {
+            self.enum_types.push(EnumDefinition {
+                name: name.to_string(),
+                constants: constants.iter().map(|c| c.to_string()).collect(),
+            });
+        }
+        TypeDefinition {
+            kind: TypeKind::Enum(name.to_string()),
+            required: true,
+        }
+    }
+}
+
+macro_rules! scalar {
+    ($($ty:ty => $name:literal),* $(,)?) => {
+        $(
+            impl AbiType for $ty {
+                fn type_definition(_: &mut Abi) -> TypeDefinition {
+                    TypeDefinition {
+                        kind: TypeKind::Scalar($name.to_string()),
+                        required: true,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+scalar! {
+    u8 => "UInt8",
+    u16 => "UInt16",
+    u32 => "UInt",
+    u64 => "UInt64",
+    i8 => "Int8",
+    i16 => "Int16",
+    i32 => "Int",
+    i64 => "Int64",
+    f32 => "Float32",
+    f64 => "Float64",
+    String => "String",
+    bool => "Boolean",
+    ByteBuf => "Bytes",
+    BigIntWrapper => "BigInt",
+    BigNumber => "BigNumber",
+    JSONString => "JSON",
+}
+
+impl<T: AbiType> AbiType for Option<T> {
+    fn type_definition(abi: &mut Abi) -> TypeDefinition {
+        TypeDefinition {
+            required: false,
+            ..T::type_definition(abi)
+        }
+    }
+}
+
+/// Boxing doesn't show in the ABI, it's how objects refer to themselves.
+impl<T: AbiType> AbiType for Box<T> {
+    fn type_definition(abi: &mut Abi) -> TypeDefinition {
+        T::type_definition(abi)
+    }
+}
+
+impl<T: AbiType> AbiType for Vec<T> {
+    fn type_definition(abi: &mut Abi) -> TypeDefinition {
+        TypeDefinition {
+            kind: TypeKind::Array(Box::new(T::type_definition(abi))),
+            required: true,
+        }
+    }
+}
+
+impl<K: AbiType, V: AbiType> AbiType for Map<K, V> {
+    fn type_definition(abi: &mut Abi) -> TypeDefinition {
+        TypeDefinition {
+            kind: TypeKind::Map(
+                Box::new(K::type_definition(abi)),
+                Box::new(V::type_definition(abi)),
+            ),
+            required: true,
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{Value, GENERIC_MAP_EXT};
+use serde_json::{Number, Value as Json};
+
+impl Value {
+    /// Converts JSON to msgpack without type information: objects become maps, numbers the closest msgpack type.
+    pub fn from_json(json: &Json) -> Value {
+        match json {
+            Json::Null => Value::Nil,
+            Json::Bool(value) => Value::Bool(*value),
+            Json::Number(number) => {
+                if let Some(value) = number.as_u64() {
+                    Value::UInt(value)
+                } else if let Some(value) = number.as_i64() {
+                    Value::Int(value)
+                } else {
+                    Value::F64(number.as_f64().unwrap_or_default())
+                }
+            }
+            Json::String(value) => Value::String(value.clone()),
+            Json::Array(items) => Value::Array(items.iter().map(Value::from_json).collect()),
+            Json::Object(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| (Value::String(key.clone()), Value::from_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Converts msgpack to JSON without type information. Bytes become arrays of numbers.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Value::Nil => Json::Null,
+            Value::Bool(value) => Json::Bool(*value),
+            Value::UInt(value) => Json::from(*value),
+            Value::Int(value) => Json::from(*value),
+            Value::F32(value) => float(*value as f64),
+            Value::F64(value) => float(*value),
+            Value::String(value) => Json::String(value.clone()),
+            Value::Binary(bytes) => Json::Array(bytes.iter().map(|b| Json::from(*b)).collect()),
+            Value::Array(items) => Json::Array(items.iter().map(Value::to_json).collect()),
+            Value::Map(entries) => Json::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.to_json_key(), value.to_json()))
+                    .collect(),
+            ),
+            Value::Ext(GENERIC_MAP_EXT, data) => Value::decode(data)
+                .map(|map| map.to_json())
+                .unwrap_or(Json::Null),
+            Value::Ext(_, data) => Json::Array(data.iter().map(|b| Json::from(*b)).collect()),
+        }
+    }
+
+    /// The value as a JSON object key, strings are used as is.
+    pub fn to_json_key(&self) -> String {
+        match self {
+            Value::String(key) => key.clone(),
+            key => key.to_json().to_string(),
+        }
+    }
+}
+
+fn float(value: f64) -> Json {
+    Number::from_f64(value).map_or(Json::Null, Json::Number)
+}
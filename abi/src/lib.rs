@@ -0,0 +1,9 @@
+//! The wrap ABI model and msgpack values, shared by the client and its macros so both see a wrap the same way.
+
+mod abi;
+pub use abi::*;
+mod abi_type;
+pub use abi_type::*;
+mod json;
+mod value;
+pub use value::*;
@@ -0,0 +1,560 @@
+use polywrap_msgpack_serde::Error;
+
+/// Msgpack extension type wraps use for `Map<K, V>`.
+pub const GENERIC_MAP_EXT: i8 = 1;
+
+/// Deeper values are rejected when decoding, so untrusted input can't overflow the stack. Low enough for
+/// unoptimized builds on 2 MiB threads, like tokio's workers.
+const MAX_DEPTH: usize = 128;
+
+/// A msgpack value, for invoking wraps whose types aren't known at compile time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    /// Decoded from the unsigned formats and positive fixints
+    UInt(u64),
+    /// Decoded from the signed formats and negative fixints
+    Int(i64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    Binary(Vec<u8>),
+    Array(Vec<Value>),
+    /// Entries in encoding order. Wrap objects are maps keyed by field name.
+    Map(Vec<(Value, Value)>),
+    /// Wraps encode `Map<K, V>` as a map inside extension type 1
+    Ext(i8, Vec<u8>),
+}
+
+impl Value {
+    /// Decodes a single msgpack value spanning all of `bytes`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader { bytes, position: 0 };
+        let value = reader.read_value(0)?;
+        if reader.position != bytes.len() {
+            return Err(Error::TrailingCharacters);
+        }
+        Ok(value)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.write(&mut buffer);
+        buffer
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The value of `key` if this is a map with string keys, like wrap objects.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Value::Nil => buffer.push(0xc0),
+            Value::Bool(false) => buffer.push(0xc2),
+            Value::Bool(true) => buffer.push(0xc3),
+            Value::UInt(value) => write_uint(buffer, *value),
+            Value::Int(value) if *value >= 0 => write_uint(buffer, *value as u64),
+            Value::Int(value) => write_int(buffer, *value),
+            Value::F32(value) => {
+                buffer.push(0xca);
+                buffer.extend_from_slice(&value.to_be_bytes());
+            }
+            Value::F64(value) => {
+                buffer.push(0xcb);
+                buffer.extend_from_slice(&value.to_be_bytes());
+            }
+            Value::String(value) => {
+                write_length(
+                    buffer,
+                    value.len(),
+                    Some((0xa0, 31)),
+                    Some(0xd9),
+                    0xda,
+                    0xdb,
+                );
+                buffer.extend_from_slice(value.as_bytes());
+            }
+            Value::Binary(value) => {
+                write_length(buffer, value.len(), None, Some(0xc4), 0xc5, 0xc6);
+                buffer.extend_from_slice(value);
+            }
+            Value::Array(values) => {
+                write_length(buffer, values.len(), Some((0x90, 15)), None, 0xdc, 0xdd);
+                for value in values {
+                    value.write(buffer);
+                }
+            }
+            Value::Map(entries) => {
+                write_length(buffer, entries.len(), Some((0x80, 15)), None, 0xde, 0xdf);
+                for (key, value) in entries {
+                    key.write(buffer);
+                    value.write(buffer);
+                }
+            }
+            Value::Ext(ext_type, data) => {
+                match data.len() {
+                    1 => buffer.push(0xd4),
+                    2 => buffer.push(0xd5),
+                    4 => buffer.push(0xd6),
+                    8 => buffer.push(0xd7),
+                    16 => buffer.push(0xd8),
+                    len => write_length(buffer, len, None, Some(0xc7), 0xc8, 0xc9),
+                }
+                buffer.push(*ext_type as u8);
+                buffer.extend_from_slice(data);
+            }
+        }
+    }
+}
+
+fn write_uint(buffer: &mut Vec<u8>, value: u64) {
+    if value < 0x80 {
+        buffer.push(value as u8);
+    } else if value <= u8::MAX as u64 {
+        buffer.extend_from_slice(&[0xcc, value as u8]);
+    } else if value <= u16::MAX as u64 {
+        buffer.push(0xcd);
+        buffer.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        buffer.push(0xce);
+        buffer.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        buffer.push(0xcf);
+        buffer.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn write_int(buffer: &mut Vec<u8>, value: i64) {
+    if value >= -32 {
+        buffer.push(value as i8 as u8);
+    } else if value >= i8::MIN as i64 {
+        buffer.extend_from_slice(&[0xd0, value as i8 as u8]);
+    } else if value >= i16::MIN as i64 {
+        buffer.push(0xd1);
+        buffer.extend_from_slice(&(value as i16).to_be_bytes());
+    } else if value >= i32::MIN as i64 {
+        buffer.push(0xd2);
+        buffer.extend_from_slice(&(value as i32).to_be_bytes());
+    } else {
+        buffer.push(0xd3);
+        buffer.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Writes the marker and length of a string, binary, array, map or ext. `fixed` is the marker of the
+/// fix format and its largest length, followed by the markers of the 8, 16 and 32 bit length formats.
+fn write_length(
+    buffer: &mut Vec<u8>,
+    len: usize,
+    fixed: Option<(u8, usize)>,
+    len8: Option<u8>,
+    len16: u8,
+    len32: u8,
+) {
+    match (fixed, len8) {
+        (Some((marker, max)), _) if len <= max => buffer.push(marker | len as u8),
+        (_, Some(marker)) if len <= u8::MAX as usize => {
+            buffer.extend_from_slice(&[marker, len as u8])
+        }
+        _ if len <= u16::MAX as usize => {
+            buffer.push(len16);
+            buffer.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            buffer.push(len32);
+            buffer.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn read_value(&mut self, depth: usize) -> Result<Value, Error> {
+        if depth > MAX_DEPTH {
+            return Err(Error::Message(
+                "msgpack value is nested too deeply".to_string(),
+            ));
+        }
+
+        let marker = self.read_u8()?;
+        let value = match marker {
+            0x00..=0x7f => Value::UInt(marker as u64),
+            0x80..=0x8f => self.read_map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.read_array((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => self.read_string((marker & 0x1f) as usize)?,
+            0xc0 => Value::Nil,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4 => {
+                let len = self.read_u8()? as usize;
+                Value::Binary(self.read_bytes(len)?.to_vec())
+            }
+            0xc5 => {
+                let len = self.read_u16()? as usize;
+                Value::Binary(self.read_bytes(len)?.to_vec())
+            }
+            0xc6 => {
+                let len = self.read_u32()? as usize;
+                Value::Binary(self.read_bytes(len)?.to_vec())
+            }
+            0xc7 => {
+                let len = self.read_u8()? as usize;
+                self.read_ext(len)?
+            }
+            0xc8 => {
+                let len = self.read_u16()? as usize;
+                self.read_ext(len)?
+            }
+            0xc9 => {
+                let len = self.read_u32()? as usize;
+                self.read_ext(len)?
+            }
+            0xca => Value::F32(f32::from_bits(self.read_u32()?)),
+            0xcb => Value::F64(f64::from_bits(self.read_u64()?)),
+            0xcc => Value::UInt(self.read_u8()? as u64),
+            0xcd => Value::UInt(self.read_u16()? as u64),
+            0xce => Value::UInt(self.read_u32()? as u64),
+            0xcf => Value::UInt(self.read_u64()?),
+            0xd0 => Value::Int(self.read_u8()? as i8 as i64),
+            0xd1 => Value::Int(self.read_u16()? as i16 as i64),
+            0xd2 => Value::Int(self.read_u32()? as i32 as i64),
+            0xd3 => Value::Int(self.read_u64()? as i64),
+            0xd4 => self.read_ext(1)?,
+            0xd5 => self.read_ext(2)?,
+            0xd6 => self.read_ext(4)?,
+            0xd7 => self.read_ext(8)?,
+            0xd8 => self.read_ext(16)?,
+            0xd9 => {
+                let len = self.read_u8()? as usize;
+                self.read_string(len)?
+            }
+            0xda => {
+                let len = self.read_u16()? as usize;
+                self.read_string(len)?
+            }
+            0xdb => {
+                let len = self.read_u32()? as usize;
+                self.read_string(len)?
+            }
+            0xdc => {
+                let len = self.read_u16()? as usize;
+                self.read_array(len, depth)?
+            }
+            0xdd => {
+                let len = self.read_u32()? as usize;
+                self.read_array(len, depth)?
+            }
+            0xde => {
+                let len = self.read_u16()? as usize;
+                self.read_map(len, depth)?
+            }
+            0xdf => {
+                let len = self.read_u32()? as usize;
+                self.read_map(len, depth)?
+            }
+            0xe0..=0xff => Value::Int(marker as i8 as i64),
+            0xc1 => return Err(Error::Syntax),
+        };
+        Ok(value)
+    }
+
+    fn read_array(&mut self, len: usize, depth: usize) -> Result<Value, Error> {
+        // Don't trust the length for preallocation, every element takes at least a byte
+        let mut values = Vec::with_capacity(len.min(self.remaining()));
+        for _ in 0..len {
+            values.push(self.read_value(depth + 1)?);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn read_map(&mut self, len: usize, depth: usize) -> Result<Value, Error> {
+        let mut entries = Vec::with_capacity(len.min(self.remaining() / 2));
+        for _ in 0..len {
+            let key = self.read_value(depth + 1)?;
+            let value = self.read_value(depth + 1)?;
+            entries.push((key, value));
+        }
+        Ok(Value::Map(entries))
+    }
+
+    fn read_string(&mut self, len: usize) -> Result<Value, Error> {
+        let bytes = self.read_bytes(len)?;
+        let string =
+            std::str::from_utf8(bytes).map_err(|error| Error::ExpectedString(error.to_string()))?;
+        Ok(Value::String(string.to_string()))
+    }
+
+    fn read_ext(&mut self, len: usize) -> Result<Value, Error> {
+        let ext_type = self.read_u8()? as i8;
+        Ok(Value::Ext(ext_type, self.read_bytes(len)?.to_vec()))
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], Error> {
+        if len > self.remaining() {
+            return Err(Error::Eof);
+        }
+        let bytes = &self.bytes[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes `value`, checks the header bytes and that it decodes back to itself.
+    fn assert_encodes(value: Value, header: &[u8]) {
+        let bytes = value.encode();
+        assert_eq!(&bytes[..header.len()], header, "{:?}", value);
+        assert_eq!(Value::decode(&bytes).unwrap(), value);
+    }
+
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![0x91; depth];
+        bytes.push(0xc0);
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let value = Value::Map(vec![
+            (Value::String("nil".into()), Value::Nil),
+            (
+                Value::String("bools".into()),
+                Value::Array(vec![Value::Bool(true), Value::Bool(false)]),
+            ),
+            (Value::String("uint".into()), Value::UInt(u64::MAX)),
+            (Value::String("int".into()), Value::Int(i64::MIN)),
+            (Value::String("f32".into()), Value::F32(1.5)),
+            (Value::String("f64".into()), Value::F64(-0.25)),
+            (
+                Value::String("bytes".into()),
+                Value::Binary(vec![0, 1, 255]),
+            ),
+            (Value::UInt(7), Value::String("non string key".into())),
+            (Value::String("ext".into()), Value::Ext(-3, vec![1, 2, 3])),
+        ]);
+
+        assert_eq!(Value::decode(&value.encode()).unwrap(), value);
+    }
+
+    #[test]
+    fn matches_serde_encoding() {
+        let args = polywrap_msgpack_serde::to_vec(&("a".to_string(), 300u32, -5i32)).unwrap();
+
+        let value = Value::Array(vec![
+            Value::String("a".into()),
+            Value::UInt(300),
+            Value::Int(-5),
+        ]);
+        assert_eq!(value.encode(), args);
+    }
+
+    #[test]
+    fn non_negative_ints_encode_as_uints() {
+        assert_eq!(Value::Int(200).encode(), Value::UInt(200).encode());
+        assert_eq!(
+            Value::decode(&Value::Int(5).encode()).unwrap(),
+            Value::UInt(5)
+        );
+    }
+
+    #[test]
+    fn uint_boundaries() {
+        assert_encodes(Value::UInt(0), &[0x00]);
+        assert_encodes(Value::UInt(0x7f), &[0x7f]);
+        assert_encodes(Value::UInt(0x80), &[0xcc, 0x80]);
+        assert_encodes(Value::UInt(0xff), &[0xcc, 0xff]);
+        assert_encodes(Value::UInt(0x100), &[0xcd, 0x01, 0x00]);
+        assert_encodes(Value::UInt(0xffff), &[0xcd, 0xff, 0xff]);
+        assert_encodes(Value::UInt(0x1_0000), &[0xce, 0x00, 0x01, 0x00, 0x00]);
+        assert_encodes(
+            Value::UInt(u32::MAX as u64),
+            &[0xce, 0xff, 0xff, 0xff, 0xff],
+        );
+        assert_encodes(
+            Value::UInt(u32::MAX as u64 + 1),
+            &[0xcf, 0, 0, 0, 1, 0, 0, 0, 0],
+        );
+    }
+
+    #[test]
+    fn int_boundaries() {
+        assert_encodes(Value::Int(-1), &[0xff]);
+        assert_encodes(Value::Int(-32), &[0xe0]);
+        assert_encodes(Value::Int(-33), &[0xd0, 0xdf]);
+        assert_encodes(Value::Int(i8::MIN as i64), &[0xd0, 0x80]);
+        assert_encodes(Value::Int(i8::MIN as i64 - 1), &[0xd1, 0xff, 0x7f]);
+        assert_encodes(Value::Int(i16::MIN as i64), &[0xd1, 0x80, 0x00]);
+        assert_encodes(
+            Value::Int(i16::MIN as i64 - 1),
+            &[0xd2, 0xff, 0xff, 0x7f, 0xff],
+        );
+        assert_encodes(Value::Int(i32::MIN as i64), &[0xd2, 0x80, 0x00, 0x00, 0x00]);
+        assert_encodes(
+            Value::Int(i32::MIN as i64 - 1),
+            &[0xd3, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        );
+    }
+
+    #[test]
+    fn str_boundaries() {
+        let string = |len| Value::String("a".repeat(len));
+        assert_encodes(string(0), &[0xa0]);
+        assert_encodes(string(31), &[0xbf]);
+        assert_encodes(string(32), &[0xd9, 32]);
+        assert_encodes(string(0xff), &[0xd9, 0xff]);
+        assert_encodes(string(0x100), &[0xda, 0x01, 0x00]);
+        assert_encodes(string(0xffff), &[0xda, 0xff, 0xff]);
+        assert_encodes(string(0x1_0000), &[0xdb, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn bin_boundaries() {
+        let binary = |len| Value::Binary(vec![7; len]);
+        assert_encodes(binary(0), &[0xc4, 0]);
+        assert_encodes(binary(0xff), &[0xc4, 0xff]);
+        assert_encodes(binary(0x100), &[0xc5, 0x01, 0x00]);
+        assert_encodes(binary(0xffff), &[0xc5, 0xff, 0xff]);
+        assert_encodes(binary(0x1_0000), &[0xc6, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn array_boundaries() {
+        let array = |len| Value::Array(vec![Value::Nil; len]);
+        assert_encodes(array(0), &[0x90]);
+        assert_encodes(array(15), &[0x9f]);
+        assert_encodes(array(16), &[0xdc, 0x00, 0x10]);
+        assert_encodes(array(0xffff), &[0xdc, 0xff, 0xff]);
+        assert_encodes(array(0x1_0000), &[0xdd, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn map_boundaries() {
+        let map = |len| Value::Map((0..len).map(|i| (Value::UInt(i), Value::Nil)).collect());
+        assert_encodes(map(0), &[0x80]);
+        assert_encodes(map(15), &[0x8f]);
+        assert_encodes(map(16), &[0xde, 0x00, 0x10]);
+        assert_encodes(map(0xffff), &[0xde, 0xff, 0xff]);
+        assert_encodes(map(0x1_0000), &[0xdf, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn ext_types() {
+        let ext = |ext_type, len| Value::Ext(ext_type, vec![9; len]);
+        assert_encodes(ext(1, 1), &[0xd4, 1]);
+        assert_encodes(ext(1, 2), &[0xd5, 1]);
+        assert_encodes(ext(1, 4), &[0xd6, 1]);
+        assert_encodes(ext(1, 8), &[0xd7, 1]);
+        assert_encodes(ext(1, 16), &[0xd8, 1]);
+        assert_encodes(ext(-1, 0), &[0xc7, 0, 0xff]);
+        assert_encodes(ext(-1, 3), &[0xc7, 3, 0xff]);
+        assert_encodes(ext(2, 0x100), &[0xc8, 0x01, 0x00, 2]);
+        assert_encodes(ext(2, 0x1_0000), &[0xc9, 0x00, 0x01, 0x00, 0x00, 2]);
+    }
+
+    #[test]
+    fn generic_maps_decode_from_serde() {
+        let map = polywrap_msgpack_serde::Map::from([("a".to_string(), 1u8)]);
+        let bytes = polywrap_msgpack_serde::to_vec(&map).unwrap();
+
+        let Value::Ext(GENERIC_MAP_EXT, data) = Value::decode(&bytes).unwrap() else {
+            panic!("expected a generic map ext");
+        };
+        assert_eq!(
+            Value::decode(&data).unwrap(),
+            Value::Map(vec![(Value::String("a".into()), Value::UInt(1))])
+        );
+    }
+
+    #[test]
+    fn depth_limit() {
+        assert!(Value::decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(
+            Value::decode(&nested(MAX_DEPTH + 1)),
+            Err(Error::Message(message)) if message.contains("nested too deeply")
+        ));
+    }
+
+    #[test]
+    fn truncated_input() {
+        let value = Value::Map(vec![(
+            Value::String("key".into()),
+            Value::Array(vec![
+                Value::UInt(0x1_0000),
+                Value::Binary(vec![1, 2]),
+                Value::Ext(1, vec![3]),
+            ]),
+        )]);
+        let bytes = value.encode();
+
+        for len in 0..bytes.len() {
+            assert!(
+                matches!(Value::decode(&bytes[..len]), Err(Error::Eof)),
+                "prefix of {} bytes",
+                len
+            );
+        }
+        // Lengths larger than the input are not trusted
+        assert!(matches!(
+            Value::decode(&[0xdd, 0xff, 0xff, 0xff, 0xff]),
+            Err(Error::Eof)
+        ));
+    }
+
+    #[test]
+    fn trailing_input() {
+        let mut bytes = Value::String("a".into()).encode();
+        bytes.push(0xc0);
+
+        assert!(matches!(
+            Value::decode(&bytes),
+            Err(Error::TrailingCharacters)
+        ));
+    }
+
+    #[test]
+    fn reserved_marker() {
+        assert!(matches!(Value::decode(&[0xc1]), Err(Error::Syntax)));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::names::{ident, snake_case, upper_camel_case};
+use polywrap_client_hmny_abi::{Abi, PropertyDefinition, TypeDefinition, TypeKind, Value};
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use std::path::PathBuf;
+use syn::{
+    parse::{Parse, ParseStream},
+    Ident, LitStr, Token,
+};
+
+/// `"path/to/wrap"`, optionally followed by the name of the facade.
+pub(crate) struct Input {
+    path: LitStr,
+    facade: Option<Ident>,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let facade = match input.parse::<Option<Token![,]>>()? {
+            Some(_) if !input.is_empty() => Some(input.parse()?),
+            _ => None,
+        };
+        Ok(Self { path, facade })
+    }
+}
+
+pub(crate) fn expand(input: Input) -> syn::Result<TokenStream> {
+    let span = input.path.span();
+    let root = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| syn::Error::new(span, "CARGO_MANIFEST_DIR is not set"))?;
+    let manifest_path = PathBuf::from(root)
+        .join(input.path.value())
+        .join("wrap.info");
+    let manifest = std::fs::read(&manifest_path).map_err(|error| {
+        syn::Error::new(
+            span,
+            format!("failed to read {}: {}", manifest_path.display(), error),
+        )
+    })?;
+    let abi = Abi::from_manifest(&manifest).ok_or_else(|| {
+        syn::Error::new(
+            span,
+            format!("{} has no ABI that can be parsed", manifest_path.display()),
+        )
+    })?;
+    let name = Value::decode(&manifest)
+        .ok()
+        .and_then(|manifest| Some(manifest.get("name")?.as_str()?.to_string()))
+        .unwrap_or_else(|| "wrap".to_string());
+
+    let objects = abi
+        .object_types
+        .iter()
+        .map(|object| {
+            let name = type_ident(&object.name);
+            let abi_name = &object.name;
+            let object = abi_object(&name, &object.properties)?;
+            Ok(quote! {
+                #object
+
+                impl ::polywrap_client_hmny::AbiType for #name {
+                    fn type_definition(
+                        abi: &mut ::polywrap_client_hmny::Abi,
+                    ) -> ::polywrap_client_hmny::TypeDefinition {
+                        abi.add_object_type::<Self>(#abi_name)
+                    }
+                }
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let enums = abi
+        .enum_types
+        .iter()
+        .map(|enum_type| enum_tokens(&enum_type.name, &enum_type.constants));
+
+    let mut args = vec![];
+    let mut methods = vec![];
+    for method in &abi.methods {
+        let args_name = format_ident!("Args{}", upper_camel_case(&method.name));
+        args.push(abi_object(&args_name, &method.arguments)?);
+
+        let method_ident = ident(&snake_case(&method.name));
+        let method_name = &method.name;
+        let return_type = match &method.return_type {
+            Some(return_type) => rust_type(return_type)?,
+            None => quote!(()),
+        };
+        let doc = format!("Invokes `{}`.", method_name);
+        methods.push(quote! {
+            #[doc = #doc]
+            pub async fn #method_ident(
+                &self,
+                args: #args_name,
+            ) -> ::std::result::Result<#return_type, ::polywrap_client_hmny::InvokeError> {
+                self.client.invoke(&self.uri, #method_name, args).await
+            }
+        });
+    }
+
+    let facade = input.facade.unwrap_or_else(|| type_ident(&name));
+    let facade_doc = format!(
+        "Typed bindings for the `{}` wrap, invoking it at `uri` through `client`.",
+        name
+    );
+    // Rebuilds the bindings when the manifest changes
+    let manifest_path = manifest_path.to_string_lossy().into_owned();
+
+    Ok(quote! {
+        const _: &[u8] = include_bytes!(#manifest_path);
+
+        #(#objects)*
+        #(#enums)*
+        #(#args)*
+
+        #[doc = #facade_doc]
+        #[derive(Clone)]
+        pub struct #facade {
+            client: ::polywrap_client_hmny::Client,
+            uri: ::polywrap_client_hmny::Uri,
+        }
+
+        impl #facade {
+            pub fn new(
+                client: ::polywrap_client_hmny::Client,
+                uri: ::polywrap_client_hmny::Uri,
+            ) -> Self {
+                Self { client, uri }
+            }
+
+            pub fn uri(&self) -> &::polywrap_client_hmny::Uri {
+                &self.uri
+            }
+
+            #(#methods)*
+        }
+    })
+}
+
+/// A struct with a field per property, which describes itself as an `AbiObject`.
+fn abi_object(name: &Ident, properties: &[PropertyDefinition]) -> syn::Result<TokenStream> {
+    let mut fields = vec![];
+    let mut definitions = vec![];
+    for property in properties {
+        let property_name = &property.name;
+        let field = ident(&snake_case(property_name));
+        let ty = match &property.ty.kind {
+            // A struct can't contain itself, only a pointer to itself
+            TypeKind::Object(object) if type_ident(object) == *name && !property.ty.required => {
+                quote!(::std::option::Option<::std::boxed::Box<#name>>)
+            }
+            _ => rust_type(&property.ty)?,
+        };
+        fields.push(quote! {
+            #[serde(rename = #property_name)]
+            pub #field: #ty
+        });
+        definitions.push(quote! {
+            ::polywrap_client_hmny::PropertyDefinition {
+                name: #property_name.to_string(),
+                ty: <#ty as ::polywrap_client_hmny::AbiType>::type_definition(abi),
+            }
+        });
+    }
+
+    Ok(quote! {
+        #[derive(
+            Clone,
+            Debug,
+            PartialEq,
+            ::polywrap_client_hmny::__private::serde::Serialize,
+            ::polywrap_client_hmny::__private::serde::Deserialize,
+        )]
+        #[serde(crate = "::polywrap_client_hmny::__private::serde")]
+        pub struct #name {
+            #(#fields,)*
+        }
+
+        impl ::polywrap_client_hmny::AbiObject for #name {
+            fn properties(
+                abi: &mut ::polywrap_client_hmny::Abi,
+            ) -> ::std::vec::Vec<::polywrap_client_hmny::PropertyDefinition> {
+                ::std::vec![#(#definitions),*]
+            }
+        }
+    })
+}
+
+/// Enums travel as the index of their constant.
+fn enum_tokens(abi_name: &str, constants: &[String]) -> TokenStream {
+    let name = type_ident(abi_name);
+    let variants: Vec<Ident> = constants
+        .iter()
+        .map(|constant| type_ident(constant))
+        .collect();
+    let indices = (0..constants.len() as u32).collect::<Vec<_>>();
+    let expecting = format!("an index of a {} constant", name);
+
+    quote! {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        impl ::polywrap_client_hmny::__private::serde::Serialize for #name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: ::polywrap_client_hmny::__private::serde::Serializer,
+            {
+                let index: u32 = match self {
+                    #(Self::#variants => #indices,)*
+                };
+                serializer.serialize_u32(index)
+            }
+        }
+
+        impl<'de> ::polywrap_client_hmny::__private::serde::Deserialize<'de> for #name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: ::polywrap_client_hmny::__private::serde::Deserializer<'de>,
+            {
+                use ::polywrap_client_hmny::__private::serde::de::{Error, Unexpected};
+
+                let index = <u32 as ::polywrap_client_hmny::__private::serde::Deserialize>::deserialize(
+                    deserializer,
+                )?;
+                match index {
+                    #(#indices => Ok(Self::#variants),)*
+                    index => Err(D::Error::invalid_value(
+                        Unexpected::Unsigned(index as u64),
+                        &#expecting,
+                    )),
+                }
+            }
+        }
+
+        impl ::polywrap_client_hmny::AbiType for #name {
+            fn type_definition(
+                abi: &mut ::polywrap_client_hmny::Abi,
+            ) -> ::polywrap_client_hmny::TypeDefinition {
+                abi.add_enum_type(#abi_name, &[#(#constants),*])
+            }
+        }
+    }
+}
+
+fn rust_type(ty: &TypeDefinition) -> syn::Result<TokenStream> {
+    let msgpack = quote!(::polywrap_client_hmny::__private::msgpack);
+    let inner = match &ty.kind {
+        TypeKind::Scalar(name) => match name.as_str() {
+            "UInt" | "UInt32" => quote!(u32),
+            "UInt8" => quote!(u8),
+            "UInt16" => quote!(u16),
+            "UInt64" => quote!(u64),
+            "Int" | "Int32" => quote!(i32),
+            "Int8" => quote!(i8),
+            "Int16" => quote!(i16),
+            "Int64" => quote!(i64),
+            "Float32" => quote!(f32),
+            "Float64" => quote!(f64),
+            "String" => quote!(::std::string::String),
+            "Boolean" => quote!(bool),
+            "Bytes" => quote!(#msgpack::serde_bytes::ByteBuf),
+            "BigInt" => quote!(#msgpack::BigIntWrapper),
+            "BigNumber" => quote!(#msgpack::BigNumber),
+            "JSON" => quote!(#msgpack::JSONString),
+            name => {
+                return Err(syn::Error::new(
+                    Span::call_site(),
+                    format!("unsupported scalar type {}", name),
+                ))
+            }
+        },
+        TypeKind::Object(name) | TypeKind::Enum(name) => {
+            let name = type_ident(name);
+            quote!(#name)
+        }
+        TypeKind::Array(item) => {
+            let item = rust_type(item)?;
+            quote!(::std::vec::Vec<#item>)
+        }
+        TypeKind::Map(key, value) => {
+            let key = rust_type(key)?;
+            let value = rust_type(value)?;
+            quote!(#msgpack::Map<#key, #value>)
+        }
+    };
+
+    Ok(match ty.required {
+        true => inner,
+        false => quote!(::std::option::Option<#inner>),
+    })
+}
+
+/// Imported types are named like `Namespace_Type`, which isn't a Rust type name.
+fn type_ident(name: &str) -> Ident {
+    ident(&upper_camel_case(name))
+}
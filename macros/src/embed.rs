@@ -0,0 +1,65 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::path::PathBuf;
+use syn::LitStr;
+
+pub(crate) fn expand(path: LitStr) -> syn::Result<TokenStream> {
+    let span = path.span();
+    let root = std::env::var("CARGO_MANIFEST_DIR")
+        .map_err(|_| syn::Error::new(span, "CARGO_MANIFEST_DIR is not set"))?;
+    let dir = PathBuf::from(root).join(path.value());
+    let file = |name: &str| {
+        let path = dir.join(name);
+        let path = path
+            .to_str()
+            .ok_or_else(|| syn::Error::new(span, format!("{} is not valid UTF-8", path.display())))?
+            .to_string();
+        Ok::<_, syn::Error>(LitStr::new(&path, span))
+    };
+
+    let wasm = file("wrap.wasm")?;
+    let manifest = file("wrap.info")?;
+    // Whether the wrap is signed is decided when the crate is compiled
+    let signature = match dir.join("wrap.sig").is_file() {
+        true => {
+            let signature = file("wrap.sig")?;
+            quote!(::std::option::Option::Some(include_bytes!(#signature)))
+        }
+        false => quote!(::std::option::Option::None),
+    };
+
+    Ok(quote! {
+        ::polywrap_client_hmny::EmbeddedWrap {
+            wasm: include_bytes!(#wasm),
+            manifest: include_bytes!(#manifest),
+            signature: #signature,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proc_macro2::Span;
+
+    fn expand_dir(dir: &std::path::Path) -> String {
+        let path = LitStr::new(dir.to_str().unwrap(), Span::call_site());
+        expand(path).unwrap().to_string()
+    }
+
+    #[test]
+    fn embeds_signature_when_present() {
+        let dir = std::env::temp_dir().join(format!("embed-wrap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let unsigned = expand_dir(&dir);
+        assert!(unsigned.contains("wrap.wasm") && unsigned.contains("wrap.info"));
+        assert!(!unsigned.contains("wrap.sig"));
+
+        std::fs::write(dir.join("wrap.sig"), b"signature").unwrap();
+        let signed = expand_dir(&dir);
+        assert!(signed.contains(&format!("{:?}", dir.join("wrap.sig").to_str().unwrap())));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,76 @@
+use proc_macro::TokenStream;
+
+mod bindings;
+mod embed;
+mod names;
+mod plugin;
+
+/// Generates types and a typed facade from the `wrap.info` of a wrap directory at compile time. The path is
+/// relative to the crate root, like `embed_wrap!`. The facade is named after the wrap unless a name is given.
+///
+/// ```ignore
+/// wrap_bindings!("assets/test-wrap", TestWrap);
+///
+/// let test_wrap = TestWrap::new(client, uri!("hmny-wrap/test-wrap"));
+/// let result: SampleResult = test_wrap
+///     .sample_method(ArgsSampleMethod { arg: "a".to_string() })
+///     .await?;
+/// ```
+#[proc_macro]
+pub fn wrap_bindings(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as bindings::Input);
+    bindings::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Embeds the `wrap.wasm` and `wrap.info` of a wrap directory into the binary at compile time, along with its
+/// `wrap.sig` if it has one. The path is relative to the crate root.
+///
+/// ```ignore
+/// let client = ClientBuilder::new()
+///     .add_embedded(uri!("hmny-wrap/test-wrap"), embed_wrap!("assets/test-wrap"))
+///     .load()
+///     .await?;
+/// ```
+#[proc_macro]
+pub fn embed_wrap(input: TokenStream) -> TokenStream {
+    let path = syn::parse_macro_input!(input as syn::LitStr);
+    embed::expand(path)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Turns an impl block into a plugin wrap. Every method taking `&self` becomes a wrap method named in camelCase,
+/// taking a single args struct, by value or by reference, and returning a result or a `Result` whose errors are
+/// reported as wrap errors. Args must implement `AbiObject` and results `AbiType`, like the types generated by
+/// `wrap_bindings!`, which is how the plugin's ABI is described.
+///
+/// Helpers that take `&self` but aren't wrap methods are marked `#[plugin(skip)]`.
+///
+/// The plugin converts into a `ClosureWrap`, and gains `abi()` and `manifest()` functions. The manifest is named
+/// after the type unless a name is given.
+///
+/// ```ignore
+/// struct TestPlugin;
+///
+/// #[plugin(name = "test-plugin")]
+/// impl TestPlugin {
+///     fn sample_method(&self, args: ArgsSampleMethod) -> Result<SampleResult, String> {
+///         Ok(SampleResult { result: args.arg })
+///     }
+/// }
+///
+/// let client = ClientBuilder::new()
+///     .add_closure(uri!("hmny-core/test-plugin"), TestPlugin.into())
+///     .load()
+///     .await?;
+/// ```
+#[proc_macro_attribute]
+pub fn plugin(attributes: TokenStream, item: TokenStream) -> TokenStream {
+    let attributes = syn::parse_macro_input!(attributes as plugin::Attributes);
+    let item = syn::parse_macro_input!(item as syn::ItemImpl);
+    plugin::expand(attributes, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
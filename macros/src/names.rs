@@ -0,0 +1,74 @@
+use proc_macro2::Span;
+use syn::Ident;
+
+/// `sampleMethod`, `template-wasm-rs` or `Ethereum_Connection` to `SampleMethod`, `TemplateWasmRs` and
+/// `EthereumConnection`. All caps words like enum constants only keep their first letter upper case.
+pub(crate) fn upper_camel_case(name: &str) -> String {
+    let mut result = String::new();
+    for word in name.split(|c: char| c == '_' || c == '-' || c.is_whitespace()) {
+        let mut chars = word.chars();
+        let Some(first) = chars.next() else {
+            continue;
+        };
+        result.extend(first.to_uppercase());
+        match word.chars().any(char::is_lowercase) {
+            true => result.extend(chars),
+            false => result.extend(chars.flat_map(char::to_lowercase)),
+        }
+    }
+    result
+}
+
+/// `sampleMethod` or `HTTPServer` to `sample_method` and `http_server`.
+pub(crate) fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_is_lower)
+            {
+                result.push('_');
+            }
+        }
+        match c {
+            '-' => result.push('_'),
+            c => result.extend(c.to_lowercase()),
+        }
+    }
+    result
+}
+
+/// `sample_method` to `sampleMethod`.
+pub(crate) fn camel_case(name: &str) -> String {
+    let mut result = String::new();
+    let mut upper = false;
+    for c in name.trim_start_matches("r#").trim_matches('_').chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                result.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// An identifier for `name`, escaping keywords and names that can't start an identifier.
+pub(crate) fn ident(name: &str) -> Ident {
+    if let Ok(ident) = syn::parse_str::<Ident>(name) {
+        return ident;
+    }
+    match name {
+        "self" | "Self" | "super" | "crate" => Ident::new(&format!("{}_", name), Span::call_site()),
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            Ident::new(&format!("_{}", name), Span::call_site())
+        }
+        name => Ident::new_raw(name, Span::call_site()),
+    }
+}
@@ -0,0 +1,294 @@
+use crate::names::{camel_case, snake_case};
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+    FnArg, GenericArgument, ImplItem, ImplItemFn, ItemImpl, LitStr, PathArguments, ReturnType,
+    Token, Type,
+};
+
+/// Empty, or `name = "my-plugin"`.
+pub(crate) struct Attributes {
+    name: Option<LitStr>,
+}
+
+impl Parse for Attributes {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(Self { name: None });
+        }
+        let key: syn::Ident = input.parse()?;
+        if key != "name" {
+            return Err(syn::Error::new(key.span(), "expected `name = \"...\"`"));
+        }
+        input.parse::<Token![=]>()?;
+        Ok(Self {
+            name: Some(input.parse()?),
+        })
+    }
+}
+
+pub(crate) fn expand(attributes: Attributes, mut item: ItemImpl) -> syn::Result<TokenStream> {
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item.generics.span(),
+            "plugins can't be generic",
+        ));
+    }
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new(
+            path.span(),
+            "#[plugin] goes on an inherent impl block",
+        ));
+    }
+
+    let self_ty = &item.self_ty;
+    let name = match attributes.name {
+        Some(name) => name.value(),
+        None => {
+            let ident = match self_ty.as_ref() {
+                Type::Path(path) => path.path.segments.last().map(|segment| &segment.ident),
+                _ => None,
+            };
+            let ident =
+                ident.ok_or_else(|| syn::Error::new(self_ty.span(), "plugins need a `name`"))?;
+            snake_case(&ident.to_string()).replace('_', "-")
+        }
+    };
+
+    // `abi()` and `manifest()` are generated next to the plugin's own functions
+    for impl_item in &item.items {
+        if let ImplItem::Fn(method) = impl_item {
+            let ident = &method.sig.ident;
+            if ident == "abi" || ident == "manifest" {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    format!(
+                        "`{}` is generated by #[plugin], rename this function",
+                        ident
+                    ),
+                ));
+            }
+        }
+    }
+
+    let mut definitions = vec![];
+    let mut registrations = vec![];
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(method) = impl_item else {
+            continue;
+        };
+        if take_skip_attribute(method)? {
+            continue;
+        }
+        let Some(method) = plugin_method(method)? else {
+            continue;
+        };
+        let PluginMethod {
+            ident,
+            args,
+            by_reference,
+            output,
+            fallible,
+        } = method;
+        let method_name = camel_case(&ident.to_string());
+
+        let arguments = match &args {
+            Some(args) => {
+                quote!(<#args as ::polywrap_client_hmny::AbiObject>::properties(&mut abi))
+            }
+            None => quote!(::std::vec::Vec::new()),
+        };
+        let return_type = match &output {
+            Some(output) => quote! {
+                ::std::option::Option::Some(
+                    <#output as ::polywrap_client_hmny::AbiType>::type_definition(&mut abi),
+                )
+            },
+            None => quote!(::std::option::Option::None),
+        };
+        definitions.push(quote! {
+            let arguments = #arguments;
+            let return_type = #return_type;
+            abi.methods.push(::polywrap_client_hmny::MethodDefinition {
+                name: #method_name.to_string(),
+                arguments,
+                return_type,
+            });
+        });
+
+        // Methods without args accept, and ignore, whatever they're given
+        let (pattern, input) = match &args {
+            Some(args) => (quote!(args), quote!(#args)),
+            None => (
+                quote!(_),
+                quote!(::polywrap_client_hmny::__private::serde::de::IgnoredAny),
+            ),
+        };
+        let call = match (&args, by_reference) {
+            (Some(_), true) => quote!(plugin.#ident(&args)),
+            (Some(_), false) => quote!(plugin.#ident(args)),
+            (None, _) => quote!(plugin.#ident()),
+        };
+        let call = match fallible {
+            true => call,
+            false => quote!(::std::result::Result::<_, ::std::convert::Infallible>::Ok(#call)),
+        };
+        registrations.push(quote! {
+            .add_owned_method(#method_name, {
+                let plugin = plugin.clone();
+                move |#pattern: #input| #call
+            })
+        });
+    }
+
+    if definitions.is_empty() {
+        return Err(syn::Error::new(
+            item.self_ty.span(),
+            "#[plugin] impl has no methods to export, plugin methods take `&self`",
+        ));
+    }
+
+    Ok(quote! {
+        #item
+
+        impl #self_ty {
+            /// The ABI of the plugin's methods, described by their arg and result types.
+            pub fn abi() -> ::polywrap_client_hmny::Abi {
+                let mut abi = ::polywrap_client_hmny::Abi::default();
+                #(#definitions)*
+                abi
+            }
+
+            /// The plugin's ABI as a msgpack encoded `wrap.info`.
+            pub fn manifest() -> ::std::vec::Vec<u8> {
+                Self::abi().to_manifest(#name)
+            }
+        }
+
+        impl ::std::convert::From<#self_ty> for ::polywrap_client_hmny::ClosureWrap {
+            fn from(plugin: #self_ty) -> Self {
+                let plugin = ::std::sync::Arc::new(plugin);
+                ::polywrap_client_hmny::ClosureWrap::new()
+                    .with_abi(<#self_ty>::abi())
+                    #(#registrations)*
+            }
+        }
+    })
+}
+
+struct PluginMethod {
+    ident: syn::Ident,
+    /// The type of the args, without the reference if they're borrowed
+    args: Option<Type>,
+    by_reference: bool,
+    /// `None` for methods returning nothing
+    output: Option<Type>,
+    /// Set if the method returns a `Result`, whose error is reported as a wrap error
+    fallible: bool,
+}
+
+/// Removes `#[plugin(skip)]` from the method, returning whether it was there.
+fn take_skip_attribute(method: &mut ImplItemFn) -> syn::Result<bool> {
+    let mut skip = false;
+    let mut error = None;
+    method.attrs.retain(|attribute| {
+        if !attribute.path().is_ident("plugin") {
+            return true;
+        }
+        match attribute.parse_args::<syn::Ident>() {
+            Ok(ident) if ident == "skip" => skip = true,
+            _ => {
+                error = Some(syn::Error::new(
+                    attribute.span(),
+                    "expected `#[plugin(skip)]`",
+                ))
+            }
+        }
+        false
+    });
+    match error {
+        Some(error) => Err(error),
+        None => Ok(skip),
+    }
+}
+
+/// Methods taking `&self` are plugin methods, unless marked `#[plugin(skip)]`. Anything else in the impl
+/// block is left alone.
+fn plugin_method(method: &ImplItemFn) -> syn::Result<Option<PluginMethod>> {
+    let signature = &method.sig;
+    let mut inputs = signature.inputs.iter();
+    match inputs.next() {
+        Some(FnArg::Receiver(receiver))
+            if receiver.reference.is_some() && receiver.mutability.is_none() => {}
+        _ => return Ok(None),
+    }
+    if let Some(asyncness) = &signature.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span(),
+            "plugin methods can't be async",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            signature.generics.span(),
+            "plugin methods can't be generic",
+        ));
+    }
+
+    let (args, by_reference) = match inputs.next() {
+        Some(FnArg::Typed(args)) => match args.ty.as_ref() {
+            Type::Reference(reference) if reference.mutability.is_none() => {
+                (Some(reference.elem.as_ref().clone()), true)
+            }
+            ty => (Some(ty.clone()), false),
+        },
+        Some(arg) => return Err(syn::Error::new(arg.span(), "unexpected argument")),
+        None => (None, false),
+    };
+    if let Some(arg) = inputs.next() {
+        return Err(syn::Error::new(
+            arg.span(),
+            "plugin methods take their args as a single struct",
+        ));
+    }
+
+    let (output, fallible) = match &signature.output {
+        ReturnType::Default => (None, false),
+        ReturnType::Type(_, ty) => match result_ok_type(ty) {
+            Some(Type::Tuple(tuple)) if tuple.elems.is_empty() => (None, true),
+            Some(ok) => (Some(ok.clone()), true),
+            None => match ty.as_ref() {
+                Type::Tuple(tuple) if tuple.elems.is_empty() => (None, false),
+                ty => (Some(ty.clone()), false),
+            },
+        },
+    };
+
+    Ok(Some(PluginMethod {
+        ident: signature.ident.clone(),
+        args,
+        by_reference,
+        output,
+        fallible,
+    }))
+}
+
+/// `T` if `ty` is written `Result<T, E>`.
+fn result_ok_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first()? {
+        GenericArgument::Type(ok) => Some(ok),
+        _ => None,
+    }
+}
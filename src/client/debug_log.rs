@@ -0,0 +1,83 @@
+use polywrap_uri::Uri;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+/// Receives the messages wraps log through `wrap_debug_log`.
+pub trait DebugLogHandler: Send + Sync {
+    fn log(&self, uri: &Uri, method: &str, message: &str);
+}
+
+impl<F: Fn(&Uri, &str, &str) + Send + Sync> DebugLogHandler for F {
+    fn log(&self, uri: &Uri, method: &str, message: &str) {
+        self(uri, method, message)
+    }
+}
+
+/// Writes debug logs to stderr, prefixed with the wrap and method that emitted them.
+pub struct StderrDebugLog;
+
+impl DebugLogHandler for StderrDebugLog {
+    fn log(&self, uri: &Uri, method: &str, message: &str) {
+        eprintln!("[{}.{}] {}", uri, method, message);
+    }
+}
+
+/// Drops debug logs. They are still emitted as `tracing` events.
+pub struct DiscardDebugLog;
+
+impl DebugLogHandler for DiscardDebugLog {
+    fn log(&self, _uri: &Uri, _method: &str, _message: &str) {}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DebugLogEntry {
+    pub uri: Uri,
+    pub method: String,
+    pub message: String,
+}
+
+/// Keeps the last `capacity` debug logs in memory. Clones share the same buffer,
+/// so a clone can be kept around to inspect what wraps logged.
+#[derive(Clone)]
+pub struct RingBufferDebugLog {
+    capacity: usize,
+    entries: Arc<Mutex<VecDeque<DebugLogEntry>>>,
+}
+
+impl RingBufferDebugLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    /// The logs currently in the buffer, oldest first.
+    pub fn entries(&self) -> Vec<DebugLogEntry> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+impl DebugLogHandler for RingBufferDebugLog {
+    fn log(&self, uri: &Uri, method: &str, message: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(DebugLogEntry {
+            uri: uri.clone(),
+            method: method.to_string(),
+            message: message.to_string(),
+        });
+    }
+}
@@ -1,27 +1,574 @@
-use std::path::PathBuf;
+use crate::{BacktraceFrame, IntegrityMismatch};
+use polywrap_uri::Uri;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Write},
+    path::PathBuf,
+    sync::Arc,
+};
 
 #[derive(Debug)]
 pub enum LoadError {
     WrapNotFound(PathBuf),
+    ReadFailed(PathBuf, std::io::Error),
     InvalidWasm(wasmer::CompileError),
+    /// The wrap package archive is malformed, too large or unsafe to extract
+    InvalidArchive(String),
+    /// The path is outside of the directories a resolver is allowed to load from
+    PathNotAllowed(PathBuf),
+    Http(reqwest::Error),
+    /// The response to the url is larger than a wrap file can be
+    ResponseTooLarge(String),
+    CacheWriteFailed(PathBuf),
+    /// Registry uris must look like `wrap://registry/<name>@<version range>`
+    InvalidRegistryUri(String),
+    RegistryIndexNotFound(PathBuf),
+    InvalidRegistryIndex(String),
+    /// The wrap doesn't match the hashes pinned in the lockfile, or isn't pinned at all
+    IntegrityMismatch(Box<IntegrityMismatch>),
+    /// Signatures are required but the wrap has none
+    Unsigned(Uri),
+    /// The wrap's signature wasn't made by any trusted key
+    InvalidSignature(Uri),
+    LockfileNotFound(PathBuf),
+    LockfileWriteFailed(PathBuf),
+    InvalidLockfile(String),
+    /// The redirect chain loops back on itself
+    RedirectCycle(Vec<Uri>),
 }
 
-#[derive(Debug)]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrapNotFound(path) => write!(f, "wrap not found at {}", path.display()),
+            Self::ReadFailed(path, _) => write!(f, "failed to read {}", path.display()),
+            Self::InvalidWasm(_) => write!(f, "invalid wasm module"),
+            Self::InvalidArchive(reason) => write!(f, "invalid wrap archive: {}", reason),
+            Self::PathNotAllowed(path) => {
+                write!(f, "loading wraps from {} is not allowed", path.display())
+            }
+            Self::Http(_) => write!(f, "failed to fetch wrap over http"),
+            Self::ResponseTooLarge(url) => write!(f, "response from {} is too large", url),
+            Self::CacheWriteFailed(path) => {
+                write!(f, "failed to write wrap cache at {}", path.display())
+            }
+            Self::InvalidRegistryUri(uri) => write!(f, "invalid registry uri {}", uri),
+            Self::RegistryIndexNotFound(path) => {
+                write!(f, "registry index not found at {}", path.display())
+            }
+            Self::InvalidRegistryIndex(reason) => write!(f, "invalid registry index: {}", reason),
+            Self::IntegrityMismatch(mismatch) => match &mismatch.expected {
+                Some(expected) => write!(
+                    f,
+                    "{} does not match its lockfile entry (expected wasm {} and manifest {}, found wasm {} and manifest {})",
+                    mismatch.uri,
+                    expected.wasm,
+                    expected.manifest,
+                    mismatch.found.wasm,
+                    mismatch.found.manifest
+                ),
+                None => write!(f, "{} is not pinned in the lockfile", mismatch.uri),
+            },
+            Self::Unsigned(uri) => write!(f, "{} is not signed", uri),
+            Self::InvalidSignature(uri) => write!(f, "{} is not signed by a trusted key", uri),
+            Self::LockfileNotFound(path) => write!(f, "lockfile not found at {}", path.display()),
+            Self::LockfileWriteFailed(path) => {
+                write!(f, "failed to write lockfile at {}", path.display())
+            }
+            Self::InvalidLockfile(reason) => write!(f, "invalid lockfile: {}", reason),
+            Self::RedirectCycle(chain) => write!(f, "redirect cycle: {}", format_chain(chain)),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidWasm(e) => Some(e),
+            Self::ReadFailed(_, e) => Some(e),
+            Self::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum InvokeError {
-    // MemoryTooSmall(usize),
-    // CallFailed(wasmer::RuntimeError),
-    // WrapError(WrapError),
-    // DecodeFailed(String),
-    // EncodeFailed(String),
-    MethodNotFound,
-    MsgpackSerialize(polywrap_msgpack_serde::Error),
-    MsgpackDeserialize(polywrap_msgpack_serde::Error),
-    WrapNotLoaded,
-    RuntimeError(wasmer::RuntimeError),
+    /// The wasm memory can't hold the data exchanged with the host
+    MemoryTooSmall {
+        uri: Uri,
+        method: String,
+        memory_size: u64,
+        /// The wasm frames of the trap, innermost first
+        backtrace: Vec<BacktraceFrame>,
+    },
+    /// The wasm module trapped or a host import failed
+    CallFailed {
+        uri: Uri,
+        method: String,
+        source: wasmer::RuntimeError,
+        /// The wasm frames of the trap, innermost first
+        backtrace: Vec<BacktraceFrame>,
+    },
+    /// The wrap aborted, usually because of a panic inside the wrap
+    WrapAbort {
+        uri: Uri,
+        method: String,
+        message: String,
+        file: String,
+        line: u32,
+        column: u32,
+        /// The wasm frames of the abort, innermost first
+        backtrace: Vec<BacktraceFrame>,
+    },
+    /// The wrap reported an error
+    WrapError {
+        uri: Uri,
+        method: String,
+        message: String,
+    },
+    /// Args or results couldn't be decoded from msgpack
+    DecodeFailed {
+        uri: Uri,
+        method: String,
+        source: polywrap_msgpack_serde::Error,
+    },
+    /// Args or results couldn't be encoded to msgpack
+    EncodeFailed {
+        uri: Uri,
+        method: String,
+        source: polywrap_msgpack_serde::Error,
+    },
+    MethodNotFound {
+        uri: Uri,
+        method: String,
+    },
+    /// The args don't match the arguments the wrap's ABI declares
+    InvalidArgs {
+        uri: Uri,
+        method: String,
+        /// Where the mismatch is, e.g. `items[2].name`
+        path: String,
+        expected: String,
+        found: String,
+    },
+    /// The result doesn't match the return type the wrap's ABI declares
+    InvalidResult {
+        uri: Uri,
+        method: String,
+        path: String,
+        expected: String,
+        found: String,
+    },
+    /// No wrap is loaded or resolvable at the end of the redirect chain
+    WrapNotLoaded {
+        uri: Uri,
+        method: String,
+        redirect_chain: Vec<Uri>,
+    },
+    /// The redirect chain loops back on itself
+    RedirectCycle {
+        uri: Uri,
+        method: String,
+        redirect_chain: Vec<Uri>,
+    },
+    /// A resolver failed while resolving the uri
+    ResolutionFailed {
+        uri: Uri,
+        method: String,
+        source: Arc<LoadError>,
+    },
+    /// The wrap failed because one of its subinvocations failed
+    Subinvocation {
+        uri: Uri,
+        method: String,
+        source: Box<InvokeError>,
+    },
+    /// The invocation shared its execution with identical concurrent invocations, which failed
+    Coalesced {
+        uri: Uri,
+        method: String,
+        source: Arc<InvokeError>,
+    },
+    /// The batch the invocation belonged to failed before invoking it, e.g. because the uri didn't resolve
+    BatchFailed {
+        uri: Uri,
+        method: String,
+        source: Arc<InvokeError>,
+    },
+    /// The invocation was cancelled before it finished, e.g. because the runtime shut down
+    Cancelled {
+        uri: Uri,
+        method: String,
+    },
 }
 
 impl InvokeError {
-    pub fn from_runtime_error(err: String) -> Self {
-        Self::RuntimeError(wasmer::RuntimeError::new(err))
+    pub fn uri(&self) -> &Uri {
+        match self {
+            Self::MemoryTooSmall { uri, .. }
+            | Self::CallFailed { uri, .. }
+            | Self::WrapAbort { uri, .. }
+            | Self::WrapError { uri, .. }
+            | Self::DecodeFailed { uri, .. }
+            | Self::EncodeFailed { uri, .. }
+            | Self::MethodNotFound { uri, .. }
+            | Self::InvalidArgs { uri, .. }
+            | Self::InvalidResult { uri, .. }
+            | Self::WrapNotLoaded { uri, .. }
+            | Self::RedirectCycle { uri, .. }
+            | Self::ResolutionFailed { uri, .. }
+            | Self::Subinvocation { uri, .. }
+            | Self::Coalesced { uri, .. }
+            | Self::BatchFailed { uri, .. }
+            | Self::Cancelled { uri, .. } => uri,
+        }
+    }
+
+    /// The (uri, method) frames the error passed through, from the outermost invocation to the one that failed.
+    pub fn stack(&self) -> Vec<(&Uri, &str)> {
+        match self {
+            Self::Subinvocation {
+                uri,
+                method,
+                source,
+            } => {
+                let mut stack = vec![(uri, method.as_str())];
+                stack.extend(source.stack());
+                stack
+            }
+            Self::Coalesced { source, .. } | Self::BatchFailed { source, .. } => source.stack(),
+            _ => vec![(self.uri(), self.method())],
+        }
+    }
+
+    /// The error at the bottom of the subinvocation stack.
+    pub fn root_cause(&self) -> &InvokeError {
+        match self {
+            Self::Subinvocation { source, .. } => source.root_cause(),
+            Self::Coalesced { source, .. } | Self::BatchFailed { source, .. } => {
+                source.root_cause()
+            }
+            _ => self,
+        }
+    }
+
+    /// Encodes the error as the string handed to a wrap through `__wrap_subinvoke_error`.
+    ///
+    /// The string starts with the human readable error, followed by the structured stack. Wraps usually embed the
+    /// subinvoke error in the error they report, which lets [`InvokeError::from_wrap_message`] recover the stack.
+    pub fn encode(&self) -> String {
+        let encoded = EncodedInvokeError {
+            stack: self
+                .stack()
+                .into_iter()
+                .map(|(uri, method)| (uri.to_string(), method.to_string()))
+                .collect(),
+            message: self.root_cause().message(),
+        };
+        // Serializing strings to json can't fail
+        let json = serde_json::to_string(&encoded).unwrap();
+        format!("{}\n{}{}{}", self, ENCODED_START, json, ENCODED_END)
+    }
+
+    /// Recovers an error produced by [`InvokeError::encode`] that is embedded in a message reported by a wrap.
+    pub fn decode(message: &str) -> Option<Self> {
+        EncodedInvokeError::find(message).and_then(EncodedInvokeError::into_error)
+    }
+
+    /// Builds the error for a message reported by the wrap at `uri`, restoring the subinvocation stack if the
+    /// message embeds an error produced by [`InvokeError::encode`].
+    pub fn from_wrap_message(uri: Uri, method: String, message: String) -> Self {
+        match Self::decode(&message) {
+            Some(source) => Self::Subinvocation {
+                uri,
+                method,
+                source: Box::new(source),
+            },
+            None => Self::WrapError {
+                uri,
+                method,
+                message,
+            },
+        }
+    }
+
+    /// The error message without the uri and method.
+    fn message(&self) -> String {
+        match self {
+            Self::MemoryTooSmall {
+                memory_size,
+                backtrace,
+                ..
+            } => with_backtrace(
+                format!("wasm memory of {} bytes is too small", memory_size),
+                backtrace,
+            ),
+            Self::CallFailed {
+                source, backtrace, ..
+            } => with_backtrace(source.message(), backtrace),
+            Self::WrapAbort {
+                message,
+                file,
+                line,
+                column,
+                backtrace,
+                ..
+            } => with_backtrace(
+                format!("wrap aborted: {} ({}:{}:{})", message, file, line, column),
+                backtrace,
+            ),
+            Self::WrapError { message, .. } => message.clone(),
+            Self::DecodeFailed { .. } => "msgpack decoding failed".to_string(),
+            Self::EncodeFailed { .. } => "msgpack encoding failed".to_string(),
+            Self::MethodNotFound { .. } => "method not found".to_string(),
+            Self::InvalidArgs {
+                path,
+                expected,
+                found,
+                ..
+            } => format!(
+                "invalid args: {}: expected {}, found {}",
+                path, expected, found
+            ),
+            Self::InvalidResult {
+                path,
+                expected,
+                found,
+                ..
+            } => format!(
+                "invalid result: {}: expected {}, found {}",
+                path, expected, found
+            ),
+            Self::WrapNotLoaded { redirect_chain, .. } => {
+                format!("no wrap found at {}", format_chain(redirect_chain))
+            }
+            Self::RedirectCycle { redirect_chain, .. } => {
+                format!("redirect cycle: {}", format_chain(redirect_chain))
+            }
+            Self::ResolutionFailed { .. } => "uri resolution failed".to_string(),
+            Self::Cancelled { .. } => "cancelled".to_string(),
+            Self::Subinvocation { .. } | Self::Coalesced { .. } | Self::BatchFailed { .. } => {
+                self.root_cause().message()
+            }
+        }
+    }
+
+    pub fn method(&self) -> &str {
+        match self {
+            Self::MemoryTooSmall { method, .. }
+            | Self::CallFailed { method, .. }
+            | Self::WrapAbort { method, .. }
+            | Self::WrapError { method, .. }
+            | Self::DecodeFailed { method, .. }
+            | Self::EncodeFailed { method, .. }
+            | Self::MethodNotFound { method, .. }
+            | Self::InvalidArgs { method, .. }
+            | Self::InvalidResult { method, .. }
+            | Self::WrapNotLoaded { method, .. }
+            | Self::RedirectCycle { method, .. }
+            | Self::ResolutionFailed { method, .. }
+            | Self::Subinvocation { method, .. }
+            | Self::Coalesced { method, .. }
+            | Self::BatchFailed { method, .. }
+            | Self::Cancelled { method, .. } => method,
+        }
+    }
+}
+
+impl fmt::Display for InvokeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Self::Coalesced { source, .. } | Self::BatchFailed { source, .. } = self {
+            return write!(f, "{}", source);
+        }
+
+        // Render subinvocations like a backtrace, innermost frame first
+        if let Self::Subinvocation { .. } = self {
+            write!(f, "{}", self.root_cause())?;
+            for (uri, method) in self.stack().iter().rev().skip(1) {
+                write!(f, "\n    at {}.{}", uri, method)?;
+            }
+            return Ok(());
+        }
+
+        write!(
+            f,
+            "failed to invoke {}.{}: {}",
+            self.uri(),
+            self.method(),
+            self.message()
+        )
+    }
+}
+
+impl Error for InvokeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CallFailed { source, .. } => Some(source),
+            Self::DecodeFailed { source, .. } | Self::EncodeFailed { source, .. } => Some(source),
+            Self::ResolutionFailed { source, .. } => Some(source.as_ref()),
+            Self::Subinvocation { source, .. } => Some(source.as_ref()),
+            Self::Coalesced { source, .. } | Self::BatchFailed { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+const ENCODED_START: &str = "<wrap-error-stack>";
+const ENCODED_END: &str = "</wrap-error-stack>";
+
+/// What survives of an `InvokeError` once it has passed through a wrap as a string.
+#[derive(Serialize, Deserialize)]
+struct EncodedInvokeError {
+    /// (uri, method) frames, outermost first
+    stack: Vec<(String, String)>,
+    message: String,
+}
+
+impl EncodedInvokeError {
+    fn find(message: &str) -> Option<Self> {
+        let start = message.find(ENCODED_START)? + ENCODED_START.len();
+        let end = start + message[start..].find(ENCODED_END)?;
+        serde_json::from_str(&message[start..end]).ok()
+    }
+
+    /// Rebuilds the chain of `Subinvocation` errors. The root cause's original type is lost, so it becomes a
+    /// `WrapError` with the original message.
+    fn into_error(self) -> Option<InvokeError> {
+        let mut frames = self
+            .stack
+            .into_iter()
+            .map(|(uri, method)| Uri::try_from(uri).ok().map(|uri| (uri, method)))
+            .collect::<Option<Vec<_>>>()?;
+
+        let (uri, method) = frames.pop()?;
+        let mut error = InvokeError::WrapError {
+            uri,
+            method,
+            message: self.message,
+        };
+        while let Some((uri, method)) = frames.pop() {
+            error = InvokeError::Subinvocation {
+                uri,
+                method,
+                source: Box::new(error),
+            };
+        }
+        Some(error)
+    }
+}
+
+fn with_backtrace(mut message: String, backtrace: &[BacktraceFrame]) -> String {
+    for (i, frame) in backtrace.iter().enumerate() {
+        let _ = write!(message, "\n    {}: {}", i, frame);
+    }
+    message
+}
+
+fn format_chain(chain: &[Uri]) -> String {
+    chain
+        .iter()
+        .map(|uri| uri.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri(uri: &str) -> Uri {
+        Uri::try_from(uri.to_string()).unwrap()
+    }
+
+    /// `a.outer` subinvoked `b.middle`, which subinvoked `c.inner`, which failed.
+    fn three_frames() -> InvokeError {
+        InvokeError::Subinvocation {
+            uri: uri("wrap://ens/a.eth"),
+            method: "outer".to_string(),
+            source: Box::new(InvokeError::Subinvocation {
+                uri: uri("wrap://ens/b.eth"),
+                method: "middle".to_string(),
+                source: Box::new(InvokeError::WrapError {
+                    uri: uri("wrap://ens/c.eth"),
+                    method: "inner".to_string(),
+                    message: "out of gas".to_string(),
+                }),
+            }),
+        }
+    }
+
+    fn stack(error: &InvokeError) -> Vec<(String, String)> {
+        error
+            .stack()
+            .into_iter()
+            .map(|(uri, method)| (uri.to_string(), method.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn encode_decode_keeps_multi_frame_stack() {
+        let error = three_frames();
+
+        let decoded = InvokeError::decode(&error.encode()).unwrap();
+
+        assert_eq!(stack(&decoded), stack(&error));
+        assert_eq!(stack(&decoded).len(), 3);
+        assert_eq!(decoded.root_cause().message(), "out of gas");
+        assert_eq!(decoded.to_string(), error.to_string());
+    }
+
+    #[test]
+    fn encode_decode_keeps_root_cause_message_of_any_variant() {
+        let error = InvokeError::Subinvocation {
+            uri: uri("wrap://ens/a.eth"),
+            method: "outer".to_string(),
+            source: Box::new(InvokeError::MethodNotFound {
+                uri: uri("wrap://ens/b.eth"),
+                method: "missing".to_string(),
+            }),
+        };
+
+        let decoded = InvokeError::decode(&error.encode()).unwrap();
+
+        assert_eq!(stack(&decoded), stack(&error));
+        assert_eq!(decoded.root_cause().message(), "method not found");
+    }
+
+    #[test]
+    fn from_wrap_message_restores_embedded_stack() {
+        // Wraps usually wrap the subinvoke error in their own message
+        let message = format!(
+            "SubInvocation exception encountered: {}\nin wrap code",
+            three_frames().encode()
+        );
+
+        let error = InvokeError::from_wrap_message(
+            uri("wrap://ens/caller.eth"),
+            "run".to_string(),
+            message,
+        );
+
+        let mut expected = vec![("wrap://ens/caller.eth".to_string(), "run".to_string())];
+        expected.extend(stack(&three_frames()));
+        assert_eq!(stack(&error), expected);
+        assert_eq!(error.root_cause().message(), "out of gas");
+    }
+
+    #[test]
+    fn from_wrap_message_without_stack_is_wrap_error() {
+        let error = InvokeError::from_wrap_message(
+            uri("wrap://ens/a.eth"),
+            "run".to_string(),
+            "plain failure".to_string(),
+        );
+
+        assert!(
+            matches!(error, InvokeError::WrapError { ref message, .. } if message == "plain failure")
+        );
+        assert!(InvokeError::decode("<wrap-error-stack>not json</wrap-error-stack>").is_none());
     }
 }
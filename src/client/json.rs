@@ -0,0 +1,504 @@
+use crate::{Abi, PropertyDefinition, TypeDefinition, TypeKind, Value, GENERIC_MAP_EXT};
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use polywrap_msgpack_serde::Error;
+use serde_json::{Map, Value as Json};
+
+/// Standard base64, padding is optional.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Converts JSON args to msgpack, coercing them to the types `method` declares if the ABI is known.
+pub(crate) fn args_from_json(args: &Json, abi: Option<&Abi>, method: &str) -> Result<Value, Error> {
+    let Some((abi, method)) = abi.and_then(|abi| Some((abi, abi.method(method)?))) else {
+        return Ok(Value::from_json(args));
+    };
+    match args {
+        Json::Object(args) => coerce_properties(abi, &method.arguments, args, ""),
+        _ => Err(mismatch("args", "object", args)),
+    }
+}
+
+/// Converts a msgpack result to JSON, using the return type `method` declares if the ABI is known.
+pub(crate) fn result_to_json(result: &Value, abi: Option<&Abi>, method: &str) -> Json {
+    let return_type = abi.and_then(|abi| Some((abi, abi.method(method)?.return_type.as_ref()?)));
+    match return_type {
+        Some((abi, return_type)) => typed_to_json(abi, return_type, result),
+        None => result.to_json(),
+    }
+}
+
+fn coerce(abi: &Abi, ty: &TypeDefinition, json: &Json, path: &str) -> Result<Value, Error> {
+    if json.is_null() {
+        return match ty.required {
+            true => Err(mismatch(path, &ty.name(), json)),
+            false => Ok(Value::Nil),
+        };
+    }
+
+    match &ty.kind {
+        TypeKind::Scalar(name) => coerce_scalar(name, json, path),
+        TypeKind::Enum(name) => {
+            let Some(enum_type) = abi.enum_type(name) else {
+                return Ok(Value::from_json(json));
+            };
+            let index = match json {
+                Json::String(constant) => enum_type.constants.iter().position(|c| c == constant),
+                Json::Number(number) => number
+                    .as_u64()
+                    .map(|index| index as usize)
+                    .filter(|index| *index < enum_type.constants.len()),
+                _ => None,
+            };
+            match index {
+                Some(index) => Ok(Value::UInt(index as u64)),
+                None => Err(mismatch(path, name, json)),
+            }
+        }
+        TypeKind::Object(name) => match (abi.object_type(name), json) {
+            (Some(object), Json::Object(properties)) => {
+                coerce_properties(abi, &object.properties, properties, path)
+            }
+            (Some(_), _) => Err(mismatch(path, name, json)),
+            (None, _) => Ok(Value::from_json(json)),
+        },
+        TypeKind::Array(item) => match json {
+            Json::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, json)| coerce(abi, item, json, &format!("{}[{}]", path, i)))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            _ => Err(mismatch(path, &ty.kind.name(), json)),
+        },
+        TypeKind::Map(key_type, value_type) => match json {
+            Json::Object(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, json)| {
+                        let path = join(path, key);
+                        // JSON keys are always strings, numeric keys are parsed back
+                        let key = match &key_type.kind {
+                            TypeKind::Scalar(name) if name != "String" => {
+                                coerce_scalar(name, &Json::String(key.clone()), &path)?
+                            }
+                            _ => Value::String(key.clone()),
+                        };
+                        Ok((key, coerce(abi, value_type, json, &path)?))
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Ok(Value::Ext(GENERIC_MAP_EXT, Value::Map(entries).encode()))
+            }
+            _ => Err(mismatch(path, &ty.kind.name(), json)),
+        },
+    }
+}
+
+/// Properties the ABI doesn't know are passed through unchanged.
+fn coerce_properties(
+    abi: &Abi,
+    properties: &[PropertyDefinition],
+    json: &Map<String, Json>,
+    path: &str,
+) -> Result<Value, Error> {
+    let mut entries = vec![];
+    for property in properties {
+        match json.get(&property.name) {
+            Some(value) => entries.push((
+                Value::String(property.name.clone()),
+                coerce(abi, &property.ty, value, &join(path, &property.name))?,
+            )),
+            None if property.ty.required => {
+                return Err(Error::Message(format!(
+                    "{}: missing required {}",
+                    join(path, &property.name),
+                    property.ty.name()
+                )))
+            }
+            None => {}
+        }
+    }
+    for (name, value) in json {
+        if !properties.iter().any(|property| &property.name == name) {
+            entries.push((Value::String(name.clone()), Value::from_json(value)));
+        }
+    }
+    Ok(Value::Map(entries))
+}
+
+fn coerce_scalar(name: &str, json: &Json, path: &str) -> Result<Value, Error> {
+    let value = match name {
+        "UInt" | "UInt8" | "UInt16" | "UInt32" | "UInt64" => {
+            let max = match name {
+                "UInt8" => u8::MAX as u64,
+                "UInt16" => u16::MAX as u64,
+                "UInt64" => u64::MAX,
+                _ => u32::MAX as u64,
+            };
+            integer(json)
+                .and_then(|value| u64::try_from(value).ok())
+                .filter(|value| *value <= max)
+                .map(Value::UInt)
+        }
+        "Int" | "Int8" | "Int16" | "Int32" | "Int64" => {
+            let (min, max) = match name {
+                "Int8" => (i8::MIN as i64, i8::MAX as i64),
+                "Int16" => (i16::MIN as i64, i16::MAX as i64),
+                "Int64" => (i64::MIN, i64::MAX),
+                _ => (i32::MIN as i64, i32::MAX as i64),
+            };
+            integer(json)
+                .and_then(|value| i64::try_from(value).ok())
+                .filter(|value| (min..=max).contains(value))
+                .map(Value::Int)
+        }
+        "Float32" => float(json).map(|value| Value::F32(value as f32)),
+        "Float64" => float(json).map(Value::F64),
+        "Boolean" => json.as_bool().map(Value::Bool),
+        "String" => json.as_str().map(|s| Value::String(s.to_string())),
+        // Big numbers travel as strings so they don't lose precision
+        "BigInt" => match json {
+            Json::String(s) if is_integer(s) => Some(Value::String(s.clone())),
+            Json::Number(number) if number.is_i64() || number.is_u64() => {
+                Some(Value::String(number.to_string()))
+            }
+            _ => None,
+        },
+        "BigNumber" => match json {
+            Json::String(s) if s.parse::<f64>().is_ok() => Some(Value::String(s.clone())),
+            Json::Number(number) => Some(Value::String(number.to_string())),
+            _ => None,
+        },
+        // Bytes are given as an array of numbers, a 0x prefixed hex string or a base64 string
+        "Bytes" => match json {
+            Json::Array(items) => items
+                .iter()
+                .map(|item| item.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<_>>()
+                .map(Value::Binary),
+            Json::String(s) => match s.strip_prefix("0x") {
+                Some(s) => hex(s),
+                None => BASE64.decode(s).ok(),
+            }
+            .map(Value::Binary),
+            _ => None,
+        },
+        // JSON travels as text, a string is taken to already be that text
+        "JSON" => match json {
+            Json::String(s) => Some(Value::String(s.clone())),
+            json => Some(Value::String(json.to_string())),
+        },
+        _ => Some(Value::from_json(json)),
+    };
+    value.ok_or_else(|| mismatch(path, name, json))
+}
+
+/// Integers may be given as numbers or as decimal strings.
+fn integer(json: &Json) -> Option<i128> {
+    match json {
+        Json::Number(number) => number
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| number.as_u64().map(i128::from)),
+        Json::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Floats may be given as numbers or as strings.
+fn float(json: &Json) -> Option<f64> {
+    match json {
+        Json::Number(number) => number.as_f64(),
+        Json::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+// `is_multiple_of` is newer than the toolchains we support
+#[allow(clippy::manual_is_multiple_of)]
+fn hex(s: &str) -> Option<Vec<u8>> {
+    let even = s.len() % 2 == 0;
+    if !even {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub(crate) fn join(path: &str, name: &str) -> String {
+    match path.is_empty() {
+        true => name.to_string(),
+        false => format!("{}.{}", path, name),
+    }
+}
+
+fn mismatch(path: &str, expected: &str, found: &Json) -> Error {
+    let found = match found {
+        Json::Null => "null",
+        Json::Bool(_) => "boolean",
+        Json::Number(_) => "number",
+        Json::String(_) => "string",
+        Json::Array(_) => "array",
+        Json::Object(_) => "object",
+    };
+    Error::Message(format!("{}: expected {}, found {}", path, expected, found))
+}
+
+fn typed_to_json(abi: &Abi, ty: &TypeDefinition, value: &Value) -> Json {
+    match (&ty.kind, value) {
+        (TypeKind::Scalar(name), Value::String(json)) if name == "JSON" => {
+            serde_json::from_str(json).unwrap_or_else(|_| Json::String(json.clone()))
+        }
+        (TypeKind::Enum(name), Value::UInt(index)) => abi
+            .enum_type(name)
+            .and_then(|enum_type| enum_type.constants.get(usize::try_from(*index).ok()?))
+            .map_or_else(
+                || value.to_json(),
+                |constant| Json::String(constant.clone()),
+            ),
+        (TypeKind::Object(name), Value::Map(entries)) => {
+            let Some(object) = abi.object_type(name) else {
+                return value.to_json();
+            };
+            Json::Object(
+                entries
+                    .iter()
+                    .map(|(key, value)| {
+                        let property = object
+                            .properties
+                            .iter()
+                            .find(|property| key.as_str() == Some(property.name.as_str()));
+                        let value = match property {
+                            Some(property) => typed_to_json(abi, &property.ty, value),
+                            None => value.to_json(),
+                        };
+                        (key.to_json_key(), value)
+                    })
+                    .collect(),
+            )
+        }
+        (TypeKind::Array(item), Value::Array(items)) => Json::Array(
+            items
+                .iter()
+                .map(|value| typed_to_json(abi, item, value))
+                .collect(),
+        ),
+        (TypeKind::Map(_, value_type), Value::Ext(GENERIC_MAP_EXT, data)) => {
+            match Value::decode(data) {
+                Ok(Value::Map(entries)) => Json::Object(
+                    entries
+                        .iter()
+                        .map(|(key, value)| {
+                            (key.to_json_key(), typed_to_json(abi, value_type, value))
+                        })
+                        .collect(),
+                ),
+                _ => value.to_json(),
+            }
+        }
+        _ => value.to_json(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MethodDefinition;
+    use serde_json::json;
+
+    fn scalar(name: &str) -> TypeDefinition {
+        TypeDefinition {
+            kind: TypeKind::Scalar(name.to_string()),
+            required: true,
+        }
+    }
+
+    /// An ABI with a single method `run` taking `value` of type `ty`.
+    fn abi(ty: TypeDefinition) -> Abi {
+        Abi {
+            methods: vec![MethodDefinition {
+                name: "run".to_string(),
+                arguments: vec![PropertyDefinition {
+                    name: "value".to_string(),
+                    ty,
+                }],
+                return_type: None,
+            }],
+            ..Abi::default()
+        }
+    }
+
+    fn coerce_value(ty: &str, json: Json) -> Result<Value, Error> {
+        let args = args_from_json(&json!({ "value": json }), Some(&abi(scalar(ty))), "run")?;
+        Ok(args.get("value").unwrap().clone())
+    }
+
+    fn assert_mismatch(ty: &str, json: Json) {
+        match coerce_value(ty, json.clone()) {
+            Err(Error::Message(message)) => {
+                assert!(
+                    message.starts_with(&format!("value: expected {}", ty)),
+                    "{}",
+                    message
+                )
+            }
+            result => panic!("{} {} coerced to {:?}", ty, json, result),
+        }
+    }
+
+    #[test]
+    fn int_widths() {
+        assert_eq!(coerce_value("UInt8", json!(255)).unwrap(), Value::UInt(255));
+        assert_eq!(
+            coerce_value("UInt16", json!(65535)).unwrap(),
+            Value::UInt(65535)
+        );
+        assert_eq!(
+            coerce_value("UInt", json!("4294967295")).unwrap(),
+            Value::UInt(u32::MAX as u64)
+        );
+        assert_eq!(coerce_value("Int8", json!(-128)).unwrap(), Value::Int(-128));
+        assert_eq!(
+            coerce_value("Int16", json!(-32768)).unwrap(),
+            Value::Int(-32768)
+        );
+        assert_eq!(
+            coerce_value("Int", json!("-2147483648")).unwrap(),
+            Value::Int(i32::MIN as i64)
+        );
+        assert_eq!(
+            coerce_value("UInt64", json!(u64::MAX)).unwrap(),
+            Value::UInt(u64::MAX)
+        );
+        assert_eq!(
+            coerce_value("Int64", json!(i64::MIN.to_string())).unwrap(),
+            Value::Int(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(
+            coerce_value("Float32", json!(1.5)).unwrap(),
+            Value::F32(1.5)
+        );
+        assert_eq!(coerce_value("Float64", json!(2)).unwrap(), Value::F64(2.0));
+        assert_eq!(
+            coerce_value("Float64", json!("-0.25")).unwrap(),
+            Value::F64(-0.25)
+        );
+        assert_mismatch("Float64", json!(true));
+    }
+
+    #[test]
+    fn out_of_range_numbers() {
+        assert_mismatch("UInt8", json!(256));
+        assert_mismatch("UInt16", json!(65536));
+        assert_mismatch("UInt32", json!(4294967296u64));
+        assert_mismatch("UInt", json!(-1));
+        assert_mismatch("Int8", json!(-129));
+        assert_mismatch("Int16", json!(32768));
+        assert_mismatch("Int32", json!(2147483648u64));
+        assert_mismatch("UInt64", json!("18446744073709551616"));
+        assert_mismatch("Int64", json!(u64::MAX));
+        assert_mismatch("Int", json!(1.5));
+        assert_mismatch("Int", json!("12a"));
+    }
+
+    #[test]
+    fn big_int_strings() {
+        let big = "123456789012345678901234567890";
+        assert_eq!(
+            coerce_value("BigInt", json!(big)).unwrap(),
+            Value::String(big.to_string())
+        );
+        assert_eq!(
+            coerce_value("BigInt", json!(-5)).unwrap(),
+            Value::String("-5".to_string())
+        );
+        assert_eq!(
+            coerce_value("BigInt", json!(u64::MAX)).unwrap(),
+            Value::String(u64::MAX.to_string())
+        );
+        assert_eq!(
+            coerce_value("BigNumber", json!(1.5)).unwrap(),
+            Value::String("1.5".to_string())
+        );
+        assert_mismatch("BigInt", json!("1.5"));
+        assert_mismatch("BigInt", json!(1.5));
+        assert_mismatch("BigInt", json!("-"));
+    }
+
+    #[test]
+    fn bytes() {
+        let bytes = Value::Binary(vec![1, 2, 255]);
+        assert_eq!(coerce_value("Bytes", json!([1, 2, 255])).unwrap(), bytes);
+        assert_eq!(coerce_value("Bytes", json!("0x0102ff")).unwrap(), bytes);
+        assert_eq!(coerce_value("Bytes", json!("AQL/")).unwrap(), bytes);
+        assert_eq!(
+            coerce_value("Bytes", json!("AQI=")).unwrap(),
+            Value::Binary(vec![1, 2])
+        );
+        assert_eq!(
+            coerce_value("Bytes", json!("AQI")).unwrap(),
+            Value::Binary(vec![1, 2])
+        );
+        assert_eq!(
+            coerce_value("Bytes", json!("")).unwrap(),
+            Value::Binary(vec![])
+        );
+        assert_mismatch("Bytes", json!([256]));
+        assert_mismatch("Bytes", json!([-1]));
+        assert_mismatch("Bytes", json!("0x012"));
+        assert_mismatch("Bytes", json!("0xzz"));
+        assert_mismatch("Bytes", json!("A"));
+        assert_mismatch("Bytes", json!("not base64!"));
+    }
+
+    #[test]
+    fn json_strings_are_not_encoded_again() {
+        assert_eq!(
+            coerce_value("JSON", json!("x")).unwrap(),
+            Value::String("x".to_string())
+        );
+        assert_eq!(
+            coerce_value("JSON", json!(r#"{"a":1}"#)).unwrap(),
+            Value::String(r#"{"a":1}"#.to_string())
+        );
+        assert_eq!(
+            coerce_value("JSON", json!({ "a": 1 })).unwrap(),
+            Value::String(r#"{"a":1}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn missing_required_and_null() {
+        let abi = abi(scalar("UInt8"));
+
+        let missing = args_from_json(&json!({}), Some(&abi), "run");
+        assert!(matches!(missing, Err(Error::Message(m)) if m == "value: missing required UInt8!"));
+        assert_mismatch("UInt8", Json::Null);
+    }
+
+    #[test]
+    fn untyped_without_abi() {
+        let args = args_from_json(&json!({ "value": [1, -1, 1.5] }), None, "run").unwrap();
+
+        assert_eq!(
+            args.get("value").unwrap(),
+            &Value::Array(vec![Value::UInt(1), Value::Int(-1), Value::F64(1.5)])
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use crate::LoadError;
+use polywrap_uri::Uri;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+use tokio::fs;
+
+/// Sha256 hashes of a wrap's files, hex encoded.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrapHashes {
+    pub wasm: String,
+    pub manifest: String,
+}
+
+impl WrapHashes {
+    pub fn compute(wasm: &[u8], manifest: &[u8]) -> Self {
+        Self {
+            wasm: sha256_hex(wasm),
+            manifest: sha256_hex(manifest),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IntegrityMismatch {
+    pub uri: Uri,
+    /// `None` if the uri isn't pinned in the lockfile
+    pub expected: Option<WrapHashes>,
+    pub found: WrapHashes,
+}
+
+/// Pins the exact content of every wrap, by uri, so deployments are reproducible.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub wraps: BTreeMap<String, WrapHashes>,
+}
+
+impl Lockfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn read<P: AsRef<Path>>(path: P) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .await
+            .map_err(|_| LoadError::LockfileNotFound(path.to_path_buf()))?;
+        serde_json::from_slice(&bytes).map_err(|e| LoadError::InvalidLockfile(e.to_string()))
+    }
+
+    pub async fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), LoadError> {
+        let path = path.as_ref();
+        let mut json = serde_json::to_vec_pretty(self)
+            .map_err(|e| LoadError::InvalidLockfile(e.to_string()))?;
+        json.push(b'\n');
+        fs::write(path, json)
+            .await
+            .map_err(|_| LoadError::LockfileWriteFailed(path.to_path_buf()))
+    }
+
+    pub fn get(&self, uri: &Uri) -> Option<&WrapHashes> {
+        self.wraps.get(uri.uri())
+    }
+
+    pub fn insert(&mut self, uri: &Uri, hashes: WrapHashes) {
+        self.wraps.insert(uri.uri().to_string(), hashes);
+    }
+
+    /// Fails unless the lockfile pins `uri` to exactly `hashes`.
+    pub fn verify(&self, uri: &Uri, hashes: &WrapHashes) -> Result<(), LoadError> {
+        match self.get(uri) {
+            Some(expected) if expected == hashes => Ok(()),
+            expected => Err(LoadError::IntegrityMismatch(Box::new(IntegrityMismatch {
+                uri: uri.clone(),
+                expected: expected.cloned(),
+                found: hashes.clone(),
+            }))),
+        }
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
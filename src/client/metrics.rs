@@ -0,0 +1,292 @@
+use polywrap_uri::Uri;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0, 5.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Outcome {
+    Ok,
+    Error,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Ok => "ok",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+/// A latency histogram over [`LATENCY_BUCKETS`].
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    /// Observations per bucket, not cumulative. The last entry counts observations above every bucket.
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    pub count: u64,
+    /// Sum of all observations, in seconds
+    pub sum: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Invocation counts and latencies, recorded by the client for every `invoke_raw` call.
+#[derive(Default)]
+pub(crate) struct InvocationMetrics {
+    histograms: Mutex<HashMap<(Uri, String, Outcome), Histogram>>,
+}
+
+impl InvocationMetrics {
+    pub fn record(&self, uri: &Uri, method: &str, outcome: Outcome, duration: Duration) {
+        self.histograms
+            .lock()
+            .unwrap()
+            .entry((uri.clone(), method.to_string(), outcome))
+            .or_default()
+            .observe(duration);
+    }
+
+    pub fn snapshot(&self) -> Vec<InvocationSnapshot> {
+        let mut invocations: Vec<_> = self
+            .histograms
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((uri, method, outcome), latency)| InvocationSnapshot {
+                uri: uri.clone(),
+                method: method.clone(),
+                outcome: *outcome,
+                latency: latency.clone(),
+            })
+            .collect();
+        invocations.sort_by(|a, b| {
+            (a.uri.to_string(), &a.method, a.outcome).cmp(&(
+                b.uri.to_string(),
+                &b.method,
+                b.outcome,
+            ))
+        });
+        invocations
+    }
+}
+
+/// Instance counts of a [`LoadedWrap`](crate::LoadedWrap), shared with its instances.
+#[derive(Default)]
+pub struct InstanceMetrics {
+    /// Instances currently alive, whether pooled or checked out
+    pub(crate) live: AtomicUsize,
+    pub(crate) creation: Mutex<Histogram>,
+}
+
+impl InstanceMetrics {
+    pub fn live_instances(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    /// How long instantiating took; its count is the number of instances ever created.
+    pub fn creation(&self) -> Histogram {
+        self.creation.lock().unwrap().clone()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InvocationSnapshot {
+    pub uri: Uri,
+    pub method: String,
+    pub outcome: Outcome,
+    pub latency: Histogram,
+}
+
+#[derive(Clone, Debug)]
+pub struct WrapSnapshot {
+    pub uri: Uri,
+    /// Idle instances waiting in `cached_instances`
+    pub cached_instances: usize,
+    pub live_instances: usize,
+    pub instance_creation: Histogram,
+}
+
+/// Point-in-time view of the client's metrics, see [`Client::metrics_snapshot`](crate::Client::metrics_snapshot).
+#[derive(Clone, Debug, Default)]
+pub struct MetricsSnapshot {
+    pub invocations: Vec<InvocationSnapshot>,
+    pub wraps: Vec<WrapSnapshot>,
+}
+
+impl MetricsSnapshot {
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP wrap_invocations_total Wrap invocations.\n");
+        out.push_str("# TYPE wrap_invocations_total counter\n");
+        for invocation in &self.invocations {
+            let _ = writeln!(
+                out,
+                "wrap_invocations_total{{{}}} {}",
+                invocation_labels(invocation),
+                invocation.latency.count
+            );
+        }
+
+        out.push_str("# HELP wrap_invocation_duration_seconds Wrap invocation latency.\n");
+        out.push_str("# TYPE wrap_invocation_duration_seconds histogram\n");
+        for invocation in &self.invocations {
+            write_histogram(
+                &mut out,
+                "wrap_invocation_duration_seconds",
+                &invocation_labels(invocation),
+                &invocation.latency,
+            );
+        }
+
+        out.push_str("# HELP wrap_cached_instances Idle instances in the pool of a wrap.\n");
+        out.push_str("# TYPE wrap_cached_instances gauge\n");
+        for wrap in &self.wraps {
+            let _ = writeln!(
+                out,
+                "wrap_cached_instances{{{}}} {}",
+                wrap_labels(wrap),
+                wrap.cached_instances
+            );
+        }
+
+        out.push_str("# HELP wrap_live_instances Instances of a wrap currently alive.\n");
+        out.push_str("# TYPE wrap_live_instances gauge\n");
+        for wrap in &self.wraps {
+            let _ = writeln!(
+                out,
+                "wrap_live_instances{{{}}} {}",
+                wrap_labels(wrap),
+                wrap.live_instances
+            );
+        }
+
+        out.push_str(
+            "# HELP wrap_instance_creation_duration_seconds Time spent instantiating a wrap.\n",
+        );
+        out.push_str("# TYPE wrap_instance_creation_duration_seconds histogram\n");
+        for wrap in &self.wraps {
+            write_histogram(
+                &mut out,
+                "wrap_instance_creation_duration_seconds",
+                &wrap_labels(wrap),
+                &wrap.instance_creation,
+            );
+        }
+
+        out
+    }
+}
+
+fn invocation_labels(invocation: &InvocationSnapshot) -> String {
+    format!(
+        "uri=\"{}\",method=\"{}\",outcome=\"{}\"",
+        escape_label(&invocation.uri.to_string()),
+        escape_label(&invocation.method),
+        invocation.outcome.as_str()
+    )
+}
+
+fn wrap_labels(wrap: &WrapSnapshot) -> String {
+    format!("uri=\"{}\"", escape_label(&wrap.uri.to_string()))
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let mut cumulative = 0;
+    for (bound, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+        cumulative += count;
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"{}\"}} {}",
+            name, labels, bound, cumulative
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{}_bucket{{{},le=\"+Inf\"}} {}",
+        name, labels, histogram.count
+    );
+    let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+    let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_cumulative_histograms_with_escaped_labels() {
+        let mut latency = Histogram::default();
+        for seconds in [0.0003, 0.002, 10.0] {
+            latency.observe(Duration::from_secs_f64(seconds));
+        }
+        let snapshot = MetricsSnapshot {
+            invocations: vec![InvocationSnapshot {
+                uri: Uri::try_from("wrap://ens/a\"b\\c.eth").unwrap(),
+                method: "run\nnow".to_string(),
+                outcome: Outcome::Ok,
+                latency: latency.clone(),
+            }],
+            wraps: vec![],
+        };
+
+        let prometheus = snapshot.to_prometheus();
+
+        let labels = r#"uri="wrap://ens/a\"b\\c.eth",method="run\nnow",outcome="ok""#;
+        let metric = "wrap_invocation_duration_seconds";
+        let lines: Vec<_> = prometheus
+            .lines()
+            .filter(|line| line.starts_with(metric))
+            .collect();
+        assert_eq!(
+            &lines[..5],
+            [
+                format!("{}_bucket{{{},le=\"0.0001\"}} 0", metric, labels),
+                format!("{}_bucket{{{},le=\"0.0005\"}} 1", metric, labels),
+                format!("{}_bucket{{{},le=\"0.001\"}} 1", metric, labels),
+                format!("{}_bucket{{{},le=\"0.0025\"}} 2", metric, labels),
+                format!("{}_bucket{{{},le=\"0.005\"}} 2", metric, labels),
+            ]
+        );
+        assert_eq!(
+            &lines[LATENCY_BUCKETS.len() - 1..],
+            [
+                format!("{}_bucket{{{},le=\"5\"}} 2", metric, labels),
+                format!("{}_bucket{{{},le=\"+Inf\"}} 3", metric, labels),
+                format!("{}_sum{{{}}} {}", metric, labels, latency.sum),
+                format!("{}_count{{{}}} 3", metric, labels),
+            ]
+        );
+        assert!(prometheus.contains(&format!("wrap_invocations_total{{{}}} 3\n", labels)));
+    }
+}
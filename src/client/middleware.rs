@@ -0,0 +1,29 @@
+use crate::InvokeError;
+use async_trait::async_trait;
+use polywrap_uri::Uri;
+
+/// An invocation about to be dispatched to a wrap. Middleware may rewrite any of it.
+pub struct InvokeRequest {
+    pub uri: Uri,
+    pub method: String,
+    /// Msgpack encoded arguments
+    pub args: Vec<u8>,
+    /// Msgpack encoded env, empty if the caller didn't provide one
+    pub env: Vec<u8>,
+}
+
+/// Runs around every invocation, whether the wrap is wasm or a closure, including the
+/// subinvocations wraps make.
+///
+/// `before` hooks run in the order the middleware were added, `after` hooks in reverse order.
+#[async_trait]
+pub trait InvokeMiddleware: Send + Sync {
+    /// Returning an error aborts the invocation. The error is passed to the `after` hooks of the
+    /// middleware whose `before` already succeeded, later middleware are skipped entirely.
+    async fn before(&self, _request: &mut InvokeRequest) -> Result<(), InvokeError> {
+        Ok(())
+    }
+
+    /// `uri` and `method` are the ones the wrap was invoked with, after every `before` hook ran.
+    async fn after(&self, _uri: &Uri, _method: &str, _result: &mut Result<Vec<u8>, InvokeError>) {}
+}
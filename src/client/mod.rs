@@ -1,11 +1,35 @@
-pub use polywrap_core_macros::uri;
 use polywrap_msgpack_serde::{from_slice, to_vec};
 pub use polywrap_uri::Uri;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Instant};
+use tokio::sync::RwLock;
 
+mod debug_log;
+pub use debug_log::*;
 mod error;
 pub use error::*;
+mod json;
+use json::{args_from_json, result_to_json};
+mod lockfile;
+pub use lockfile::*;
+mod metrics;
+pub use metrics::*;
+mod middleware;
+pub use middleware::*;
+mod redirect;
+use redirect::resolve_redirect_chain;
+mod result_cache;
+pub use result_cache::*;
+mod resolver;
+pub use resolver::*;
+mod signature;
+pub use signature::*;
+mod single_flight;
+use single_flight::{coalesced, Flight, SingleFlight};
+#[cfg(test)]
+mod tests;
+mod validate;
+use validate::{validate_args, validate_result};
 mod wrap;
 pub use wrap::*;
 
@@ -22,52 +46,289 @@ impl Clone for Client {
 }
 
 struct ClientInner {
-    pub loaded_wraps: HashMap<Uri, Wrap>,
+    /// Wraps loaded up front, plus wraps lazily resolved by `resolvers`, under the uri they resolved at.
+    pub loaded_wraps: RwLock<HashMap<Uri, Arc<Wrap>>>,
+    pub redirects: HashMap<Uri, Uri>,
+    /// The uris that led to a lazily resolved wrap, to the uri it's loaded under.
+    pub resolved_redirects: RwLock<HashMap<Uri, Uri>>,
+    pub resolvers: ResolverChain,
+    /// If set, every loaded wrap must match its pinned hashes.
+    pub lockfile: Option<Lockfile>,
+    pub signature_policy: SignaturePolicy,
+    pub debug_log_handler: Arc<dyn DebugLogHandler>,
+    /// Take precedence over `debug_log_handler` for the wraps loaded under these uris.
+    pub debug_log_handlers: HashMap<Uri, Arc<dyn DebugLogHandler>>,
+    pub metrics: InvocationMetrics,
+    pub middleware: Vec<Box<dyn InvokeMiddleware>>,
+    /// Memoized results of methods declared pure, by uri then method.
+    pub result_caches: HashMap<Uri, HashMap<String, ResultCache>>,
+    /// Set if identical concurrent invocations share one execution.
+    pub single_flight: Option<SingleFlight>,
+    /// If set, args and results of wraps with a known ABI are checked against it.
+    pub validate: bool,
 }
 
 impl Client {
-    /// Ignoring env for now
+    /// Invokes with an empty env, middleware may provide one
     pub async fn invoke<Input: Serialize, Output: DeserializeOwned>(
         &self,
         uri: &Uri,
         method: &str,
         args: Input,
     ) -> Result<Output, InvokeError> {
-        let args = to_vec(&args).map_err(InvokeError::MsgpackSerialize)?;
+        let args = to_vec(&args).map_err(|source| InvokeError::EncodeFailed {
+            uri: uri.clone(),
+            method: method.to_string(),
+            source,
+        })?;
         let result = self.invoke_raw(uri, method, args).await?;
 
-        println!("result: {:?}", result);
-
-        let result = from_slice(&result).map_err(InvokeError::MsgpackDeserialize)?;
+        let result = from_slice(&result).map_err(|source| InvokeError::DecodeFailed {
+            uri: uri.clone(),
+            method: method.to_string(),
+            source,
+        })?;
 
         Ok(result)
     }
 
-    async fn invoke_raw(
+    /// Invokes with an untyped msgpack value, for callers that don't know the wrap's types at compile time.
+    pub async fn invoke_value(
+        &self,
+        uri: &Uri,
+        method: &str,
+        args: Value,
+    ) -> Result<Value, InvokeError> {
+        let result = self.invoke_raw(uri, method, args.encode()).await?;
+
+        Value::decode(&result).map_err(|source| InvokeError::DecodeFailed {
+            uri: uri.clone(),
+            method: method.to_string(),
+            source,
+        })
+    }
+
+    /// Invokes with JSON args, converted to msgpack. If the wrap's ABI is known, args are coerced to the exact
+    /// types the method declares, e.g. numbers to `BigInt` strings or arrays of numbers to `Bytes`.
+    ///
+    /// Middleware see the args before coercion, which uses the ABI of the wrap the request resolves to after
+    /// every `before` hook ran.
+    // Preparing the args fails like the invocation itself
+    #[allow(clippy::result_large_err)]
+    pub async fn invoke_json(
+        &self,
+        uri: &Uri,
+        method: &str,
+        args: serde_json::Value,
+    ) -> Result<serde_json::Value, InvokeError> {
+        let mut invoked = None;
+        let args = Value::from_json(&args).encode();
+        let result = self
+            .invoke_request(uri, method, args, |wrap, uri, method, args| {
+                invoked = Some((wrap.clone(), method.to_string()));
+                let encode_failed = |source| InvokeError::EncodeFailed {
+                    uri: uri.clone(),
+                    method: method.to_string(),
+                    source,
+                };
+                let args = Value::decode(&args).map_err(encode_failed)?.to_json();
+                let args = args_from_json(&args, wrap.abi(), method).map_err(encode_failed)?;
+                Ok(args.encode())
+            })
+            .await?;
+
+        let result = Value::decode(&result).map_err(|source| InvokeError::DecodeFailed {
+            uri: uri.clone(),
+            method: method.to_string(),
+            source,
+        })?;
+        // An `after` hook may have recovered from a failed `before` hook, the wrap is unknown then
+        Ok(match &invoked {
+            Some((wrap, method)) => result_to_json(&result, wrap.abi(), method),
+            None => result_to_json(&result, None, method),
+        })
+    }
+
+    /// Invokes with msgpack encoded args, returning the msgpack encoded result.
+    #[allow(clippy::result_large_err)]
+    pub async fn invoke_raw(
         &self,
         uri: &Uri,
         method: &str,
         args: Vec<u8>,
     ) -> Result<Vec<u8>, InvokeError> {
-        let wrap = self
+        self.invoke_request(uri, method, args, |_, _, _, args| Ok(args))
+            .await
+    }
+
+    /// Runs the middleware around an invocation. `prepare_args` gets the wrap the request resolved to and
+    /// may convert the args for it, after every `before` hook ran.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            %uri,
+            method,
+            args_size = args.len(),
+            instance_reused = tracing::field::Empty,
+        ),
+    )]
+    async fn invoke_request(
+        &self,
+        uri: &Uri,
+        method: &str,
+        args: Vec<u8>,
+        prepare_args: impl FnOnce(&Arc<Wrap>, &Uri, &str, Vec<u8>) -> Result<Vec<u8>, InvokeError>,
+    ) -> Result<Vec<u8>, InvokeError> {
+        let mut request = InvokeRequest {
+            uri: uri.clone(),
+            method: method.to_string(),
+            args,
+            env: vec![],
+        };
+        // Only the middleware whose `before` succeeded get their `after` hook
+        let mut entered = 0;
+        let mut before = Ok(());
+        for middleware in &self.inner.middleware {
+            before = middleware.before(&mut request).await;
+            if before.is_err() {
+                break;
+            }
+            entered += 1;
+        }
+
+        let InvokeRequest {
+            uri,
+            method,
+            args,
+            env,
+        } = request;
+        let mut result = match before {
+            Ok(()) => {
+                let started = Instant::now();
+                let result = async {
+                    let (wrap, resolved_uri) = self.resolve_wrap(&uri, &method).await?;
+                    let args = prepare_args(&wrap, &uri, &method, args)?;
+                    self.invoke_coalesced(&wrap, &resolved_uri, &uri, &method, args, env)
+                        .await
+                }
+                .await;
+
+                let outcome = match result {
+                    Ok(_) => Outcome::Ok,
+                    Err(_) => Outcome::Error,
+                };
+                self.inner
+                    .metrics
+                    .record(&uri, &method, outcome, started.elapsed());
+
+                result
+            }
+            Err(error) => Err(error),
+        };
+
+        for middleware in self.inner.middleware[..entered].iter().rev() {
+            middleware.after(&uri, &method, &mut result).await;
+        }
+
+        result
+    }
+
+    /// Lets identical concurrent invocations share one execution, if enabled.
+    async fn invoke_coalesced(
+        &self,
+        wrap: &Wrap,
+        resolved_uri: &Uri,
+        uri: &Uri,
+        method: &str,
+        args: Vec<u8>,
+        env: Vec<u8>,
+    ) -> Result<Vec<u8>, InvokeError> {
+        let Some(single_flight) = &self.inner.single_flight else {
+            return self
+                .invoke_cached(wrap, resolved_uri, uri, method, args, env)
+                .await;
+        };
+
+        match single_flight.join(uri, method, &args, &env) {
+            Flight::Leader(guard) => guard.finish(
+                self.invoke_cached(wrap, resolved_uri, uri, method, args, env)
+                    .await,
+            ),
+            Flight::Follower(mut receiver) => match receiver.recv().await {
+                Ok(result) => result.map_err(coalesced),
+                // The leader was cancelled before finishing
+                Err(_) => {
+                    self.invoke_cached(wrap, resolved_uri, uri, method, args, env)
+                        .await
+                }
+            },
+        }
+    }
+
+    /// Serves memoized results of methods declared pure without checking out an instance.
+    /// Invocations with an env are never memoized.
+    async fn invoke_cached(
+        &self,
+        wrap: &Wrap,
+        resolved_uri: &Uri,
+        uri: &Uri,
+        method: &str,
+        args: Vec<u8>,
+        env: Vec<u8>,
+    ) -> Result<Vec<u8>, InvokeError> {
+        let cache = self
             .inner
-            .loaded_wraps
+            .result_caches
             .get(uri)
-            .ok_or(InvokeError::WrapNotLoaded)?;
+            .and_then(|caches| caches.get(method))
+            .filter(|_| env.is_empty());
+        let Some(cache) = cache else {
+            return self
+                .invoke_wrap(wrap, resolved_uri, uri, method, args, env)
+                .await;
+        };
+
+        if let Some(result) = cache.get(&args) {
+            return Ok(result);
+        }
+        let result = self
+            .invoke_wrap(wrap, resolved_uri, uri, method, args.clone(), env)
+            .await?;
+        cache.insert(args, result.clone());
+
+        Ok(result)
+    }
+
+    async fn invoke_wrap(
+        &self,
+        wrap: &Wrap,
+        resolved_uri: &Uri,
+        uri: &Uri,
+        method: &str,
+        args: Vec<u8>,
+        env: Vec<u8>,
+    ) -> Result<Vec<u8>, InvokeError> {
+        self.validate_args(wrap, uri, method, &args)?;
 
         let result = match wrap {
             Wrap::Loaded(loaded_wrap) => {
                 // Get an instance from the cache, or create a new one if none are available.
-                let mut instance = loaded_wrap
-                    .cached_instances
-                    .lock()
-                    .await
-                    .pop()
-                    .unwrap_or_else(|| WrapInstance::new(loaded_wrap));
+                let cached_instance = loaded_wrap.cached_instances.lock().await.pop();
+                tracing::Span::current().record("instance_reused", cached_instance.is_some());
+                let mut instance =
+                    cached_instance.unwrap_or_else(|| WrapInstance::new(loaded_wrap));
 
                 // Invoke the method on the instance.
                 let result = instance
-                    .invoke(method, args, &loaded_wrap.execution_context)
+                    .invoke(
+                        uri,
+                        method,
+                        args,
+                        env,
+                        &loaded_wrap.execution_context,
+                        self.host(resolved_uri),
+                    )
                     .await?;
 
                 // Put the instance back in the cache.
@@ -75,27 +336,513 @@ impl Client {
 
                 result
             }
-            Wrap::Closure(closure_wrap) => closure_wrap.invoke(method, &args).await?,
+            Wrap::Closure(closure_wrap) => closure_wrap.invoke(uri, method, &args).await?,
         };
 
+        self.validate_result(wrap, uri, method, &result)?;
         Ok(result)
     }
+
+    /// Checks the args against the wrap's ABI, if validation is enabled and the ABI is known.
+    #[allow(clippy::result_large_err)]
+    fn validate_args(
+        &self,
+        wrap: &Wrap,
+        uri: &Uri,
+        method: &str,
+        args: &[u8],
+    ) -> Result<(), InvokeError> {
+        let Some(abi) = wrap.abi().filter(|_| self.inner.validate) else {
+            return Ok(());
+        };
+        let Some(method_definition) = abi.method(method) else {
+            return Err(InvokeError::MethodNotFound {
+                uri: uri.clone(),
+                method: method.to_string(),
+            });
+        };
+
+        validate_args(abi, &method_definition.arguments, args).map_err(|mismatch| {
+            InvokeError::InvalidArgs {
+                uri: uri.clone(),
+                method: method.to_string(),
+                path: mismatch.path,
+                expected: mismatch.expected,
+                found: mismatch.found,
+            }
+        })
+    }
+
+    /// Checks the result against the wrap's ABI, if validation is enabled and the ABI is known.
+    #[allow(clippy::result_large_err)]
+    fn validate_result(
+        &self,
+        wrap: &Wrap,
+        uri: &Uri,
+        method: &str,
+        result: &[u8],
+    ) -> Result<(), InvokeError> {
+        let return_type = wrap
+            .abi()
+            .filter(|_| self.inner.validate)
+            .and_then(|abi| Some((abi, abi.method(method)?.return_type.as_ref()?)));
+        let Some((abi, return_type)) = return_type else {
+            return Ok(());
+        };
+
+        validate_result(abi, return_type, result).map_err(|mismatch| InvokeError::InvalidResult {
+            uri: uri.clone(),
+            method: method.to_string(),
+            path: mismatch.path,
+            expected: mismatch.expected,
+            found: mismatch.found,
+        })
+    }
+
+    /// Invokes `method` once per args, returning the results in the same order.
+    ///
+    /// Wasm wraps are invoked by several workers, each checking out a single instance for all of its calls.
+    /// Result caching and coalescing don't apply to batches. If any middleware is registered, the args are
+    /// invoked one by one through the regular path so the middleware still sees every invocation.
+    // Per item results have the same error type as single invocations
+    #[allow(clippy::result_large_err)]
+    pub async fn invoke_batch<Input: Serialize, Output: DeserializeOwned>(
+        &self,
+        uri: &Uri,
+        method: &str,
+        args: Vec<Input>,
+    ) -> Vec<Result<Output, InvokeError>> {
+        let args = args
+            .iter()
+            .map(|args| {
+                to_vec(args).map_err(|source| InvokeError::EncodeFailed {
+                    uri: uri.clone(),
+                    method: method.to_string(),
+                    source,
+                })
+            })
+            .collect();
+
+        self.invoke_raw_batch(uri, method, args)
+            .await
+            .into_iter()
+            .map(|result| {
+                from_slice(&result?).map_err(|source| InvokeError::DecodeFailed {
+                    uri: uri.clone(),
+                    method: method.to_string(),
+                    source,
+                })
+            })
+            .collect()
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn invoke_raw_batch(
+        &self,
+        uri: &Uri,
+        method: &str,
+        args: Vec<Result<Vec<u8>, InvokeError>>,
+    ) -> Vec<Result<Vec<u8>, InvokeError>> {
+        let (wrap, resolved_uri) = match self.resolve_wrap(uri, method).await {
+            Ok(resolved) => resolved,
+            Err(error) => {
+                let error = Arc::new(error);
+                return args
+                    .into_iter()
+                    .map(|args| {
+                        args.and_then(|_| {
+                            Err(InvokeError::BatchFailed {
+                                uri: uri.clone(),
+                                method: method.to_string(),
+                                source: error.clone(),
+                            })
+                        })
+                    })
+                    .collect();
+            }
+        };
+
+        if !self.inner.middleware.is_empty() || !matches!(wrap.as_ref(), Wrap::Loaded(_)) {
+            let mut results = Vec::with_capacity(args.len());
+            for args in args {
+                results.push(match args {
+                    Ok(args) => self.invoke_raw(uri, method, args).await,
+                    Err(error) => Err(error),
+                });
+            }
+            return results;
+        }
+
+        let mut results: Vec<Option<Result<Vec<u8>, InvokeError>>> = Vec::new();
+        let mut jobs = Vec::new();
+        for (index, args) in args.into_iter().enumerate() {
+            match args {
+                Ok(args) => match self.validate_args(&wrap, uri, method, &args) {
+                    Ok(()) => {
+                        jobs.push((index, args));
+                        results.push(None);
+                    }
+                    Err(error) => results.push(Some(Err(error))),
+                },
+                Err(error) => results.push(Some(Err(error))),
+            }
+        }
+
+        let workers = std::thread::available_parallelism()
+            .map(|parallelism| parallelism.get())
+            .unwrap_or(1)
+            .min(jobs.len());
+        let jobs = Arc::new(std::sync::Mutex::new(jobs.into_iter()));
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let client = self.clone();
+                let wrap = wrap.clone();
+                let jobs = jobs.clone();
+                let resolved_uri = resolved_uri.clone();
+                let uri = uri.clone();
+                let method = method.to_string();
+                tokio::spawn(async move {
+                    client
+                        .batch_worker(&wrap, &resolved_uri, &uri, &method, &jobs)
+                        .await
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            let worker_results = match handle.await {
+                Ok(worker_results) => worker_results,
+                Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+                // The jobs the worker took are lost, they're reported as cancelled below
+                Err(_) => continue,
+            };
+            for (index, result) in worker_results {
+                results[index] = Some(result);
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    Err(InvokeError::Cancelled {
+                        uri: uri.clone(),
+                        method: method.to_string(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Takes jobs until there are none left, reusing one instance as long as its invocations succeed.
+    async fn batch_worker(
+        &self,
+        wrap: &Wrap,
+        resolved_uri: &Uri,
+        uri: &Uri,
+        method: &str,
+        jobs: &std::sync::Mutex<std::vec::IntoIter<(usize, Vec<u8>)>>,
+    ) -> Vec<(usize, Result<Vec<u8>, InvokeError>)> {
+        let Wrap::Loaded(loaded_wrap) = wrap else {
+            unreachable!("batches of closures are invoked one by one");
+        };
+        let host = self.host(resolved_uri);
+        let mut instance = None;
+        let mut results = vec![];
+
+        loop {
+            let job = jobs.lock().unwrap().next();
+            let Some((index, args)) = job else {
+                break;
+            };
+
+            let mut checked_out = match instance.take() {
+                Some(instance) => instance,
+                None => match loaded_wrap.cached_instances.lock().await.pop() {
+                    Some(instance) => instance,
+                    None => WrapInstance::new(loaded_wrap),
+                },
+            };
+
+            let started = Instant::now();
+            let result = checked_out
+                .invoke(
+                    uri,
+                    method,
+                    args,
+                    vec![],
+                    &loaded_wrap.execution_context,
+                    host.clone(),
+                )
+                .await;
+
+            // Like single invocations, an instance that failed is not reused
+            if result.is_ok() {
+                instance = Some(checked_out);
+            }
+            let result = match result {
+                Ok(result) => self
+                    .validate_result(wrap, uri, method, &result)
+                    .map(|()| result),
+                Err(error) => Err(error),
+            };
+            let outcome = match result {
+                Ok(_) => Outcome::Ok,
+                Err(_) => Outcome::Error,
+            };
+            self.inner
+                .metrics
+                .record(uri, method, outcome, started.elapsed());
+            results.push((index, result));
+        }
+
+        if let Some(instance) = instance {
+            loaded_wrap.cached_instances.lock().await.push(instance);
+        }
+        results
+    }
+
+    fn host(&self, uri: &Uri) -> Host {
+        Host {
+            uri: uri.clone(),
+            debug_log_handler: self.debug_log_handler(uri),
+            subinvoker: self.subinvoker(),
+        }
+    }
+
+    /// Subinvocations go through `invoke_raw`, so redirects, middleware and caching apply to them too.
+    /// Wasm calls are synchronous, so the worker thread blocks until the subinvocation is done.
+    // The subinvoker returns the same errors as `invoke_raw`
+    #[allow(clippy::result_large_err)]
+    fn subinvoker(&self) -> Subinvoker {
+        // Cached instances hold on to the subinvoker, a strong reference would keep the client alive
+        let inner = Arc::downgrade(&self.inner);
+        Arc::new(move |uri, method, args| {
+            let client = Client {
+                inner: inner
+                    .upgrade()
+                    .expect("the client outlives its invocations"),
+            };
+            let handle = tokio::runtime::Handle::current();
+            if handle.runtime_flavor() != tokio::runtime::RuntimeFlavor::MultiThread {
+                return Err(InvokeError::WrapError {
+                    uri: uri.clone(),
+                    method: method.to_string(),
+                    message: "subinvocations need the multi-threaded tokio runtime".to_string(),
+                });
+            }
+            tokio::task::block_in_place(|| handle.block_on(client.invoke_raw(uri, method, args)))
+        })
+    }
+
+    fn debug_log_handler(&self, uri: &Uri) -> Arc<dyn DebugLogHandler> {
+        self.inner
+            .debug_log_handlers
+            .get(uri)
+            .unwrap_or(&self.inner.debug_log_handler)
+            .clone()
+    }
+
+    /// Follows redirects until a wrap is found, consulting the resolver chain for uris that aren't loaded yet.
+    /// Returns the wrap and the uri it's loaded under.
+    async fn resolve_wrap(&self, uri: &Uri, method: &str) -> Result<(Arc<Wrap>, Uri), InvokeError> {
+        let resolution_failed = |source| InvokeError::ResolutionFailed {
+            uri: uri.clone(),
+            method: method.to_string(),
+            source: Arc::new(source),
+        };
+        let mut chain = vec![uri.clone()];
+
+        loop {
+            let current = chain.last().unwrap();
+
+            // Looked up before branching, so no lock is held while the resolvers run
+            let resolved_redirect = self
+                .inner
+                .resolved_redirects
+                .read()
+                .await
+                .get(current)
+                .cloned();
+            let loaded_wrap = self.inner.loaded_wraps.read().await.get(current).cloned();
+
+            let next = if let Some(to) = self.inner.redirects.get(current) {
+                to.clone()
+            } else if let Some(to) = resolved_redirect {
+                to
+            } else if let Some(wrap) = loaded_wrap {
+                return Ok((wrap, current.clone()));
+            } else {
+                match self
+                    .inner
+                    .resolvers
+                    .resolve(current)
+                    .await
+                    .map_err(resolution_failed)?
+                {
+                    UriResolution::Wrap(wrap) => {
+                        verify_wrap(
+                            self.inner.lockfile.as_ref(),
+                            &self.inner.signature_policy,
+                            current,
+                            &wrap,
+                        )
+                        .map_err(resolution_failed)?;
+
+                        // Cache the wrap and the way to it so we don't resolve it again
+                        self.inner
+                            .loaded_wraps
+                            .write()
+                            .await
+                            .insert(current.clone(), wrap.clone());
+                        let mut resolved_redirects = self.inner.resolved_redirects.write().await;
+                        for from in &chain[..chain.len() - 1] {
+                            resolved_redirects.insert(from.clone(), current.clone());
+                        }
+                        return Ok((wrap, current.clone()));
+                    }
+                    UriResolution::Redirect(to) => to,
+                    UriResolution::NotFound => {
+                        return Err(InvokeError::WrapNotLoaded {
+                            uri: uri.clone(),
+                            method: method.to_string(),
+                            redirect_chain: chain,
+                        })
+                    }
+                }
+            };
+
+            let is_cycle = chain.contains(&next);
+            chain.push(next);
+            if is_cycle {
+                return Err(InvokeError::RedirectCycle {
+                    uri: uri.clone(),
+                    method: method.to_string(),
+                    redirect_chain: chain,
+                });
+            }
+        }
+    }
+
+    /// Invocation counts and latencies, and instance pool sizes of every wasm wrap loaded so far.
+    pub async fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let mut wraps: Vec<(Uri, Arc<Wrap>)> = self
+            .inner
+            .loaded_wraps
+            .read()
+            .await
+            .iter()
+            .map(|(uri, wrap)| (uri.clone(), wrap.clone()))
+            .collect();
+
+        // The same wrap may be loaded under several uris, report it once under its first uri
+        wraps.sort_by_key(|(uri, _)| uri.to_string());
+        let mut seen: Vec<&Arc<Wrap>> = vec![];
+        let mut snapshot = MetricsSnapshot {
+            invocations: self.inner.metrics.snapshot(),
+            wraps: vec![],
+        };
+        for (uri, wrap) in &wraps {
+            let Wrap::Loaded(loaded_wrap) = wrap.as_ref() else {
+                continue;
+            };
+            if seen.iter().any(|seen| Arc::ptr_eq(seen, wrap)) {
+                continue;
+            }
+            seen.push(wrap);
+
+            snapshot.wraps.push(WrapSnapshot {
+                uri: uri.clone(),
+                cached_instances: loaded_wrap.cached_instances.lock().await.len(),
+                live_instances: loaded_wrap.instance_metrics.live_instances(),
+                instance_creation: loaded_wrap.instance_metrics.creation(),
+            });
+        }
+
+        snapshot
+    }
+
+    /// Hit and miss counts of a method's result cache, if its results are cached.
+    pub fn result_cache_stats(&self, uri: &Uri, method: &str) -> Option<ResultCacheStats> {
+        self.inner
+            .result_caches
+            .get(uri)
+            .and_then(|caches| caches.get(method))
+            .map(ResultCache::stats)
+    }
+
+    /// The trusted key that signed the wrap `uri` resolves to, resolving it if needed. `None` if the wrap isn't
+    /// signed by a trusted key, isn't a wasm wrap, or doesn't resolve.
+    pub async fn signer(&self, uri: &Uri) -> Option<VerifyingKey> {
+        let (wrap, _) = self.resolve_wrap(uri, "").await.ok()?;
+        match wrap.as_ref() {
+            Wrap::Loaded(loaded_wrap) => loaded_wrap.signer().copied(),
+            Wrap::Closure(_) => None,
+        }
+    }
+
+    /// Generates a lockfile pinning every wrap loaded so far, including wraps lazily resolved.
+    pub async fn lockfile(&self) -> Lockfile {
+        let mut lockfile = Lockfile::new();
+        self.update_lockfile(&mut lockfile).await;
+        lockfile
+    }
+
+    /// Adds or updates the pinned hashes of every wrap loaded so far, under the uri it was loaded or resolved at.
+    pub async fn update_lockfile(&self, lockfile: &mut Lockfile) {
+        for (uri, wrap) in self.inner.loaded_wraps.read().await.iter() {
+            if let Wrap::Loaded(loaded_wrap) = wrap.as_ref() {
+                lockfile.insert(uri, loaded_wrap.hashes.clone());
+            }
+        }
+    }
 }
 
 pub struct ClientBuilder {
     wraps_to_load: Vec<(Uri, LoadWrapRequest)>,
+    redirects: HashMap<Uri, Uri>,
+    resolvers: ResolverChain,
+    lockfile: Option<Lockfile>,
+    signature_policy: SignaturePolicy,
+    debug_log_handler: Arc<dyn DebugLogHandler>,
+    debug_log_handlers: HashMap<Uri, Arc<dyn DebugLogHandler>>,
+    middleware: Vec<Box<dyn InvokeMiddleware>>,
+    result_caches: HashMap<Uri, HashMap<String, ResultCacheConfig>>,
+    coalesce_invocations: bool,
+    validate: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            wraps_to_load: Vec::new(),
+            redirects: HashMap::new(),
+            resolvers: ResolverChain::default(),
+            lockfile: None,
+            signature_policy: SignaturePolicy::default(),
+            debug_log_handler: Arc::new(DiscardDebugLog),
+            debug_log_handlers: HashMap::new(),
+            middleware: Vec::new(),
+            result_caches: HashMap::new(),
+            coalesce_invocations: false,
+            validate: false,
+        }
+    }
 }
 
 enum LoadWrapRequest {
     Fs(PathBuf),
+    Bytes {
+        wasm: Vec<u8>,
+        manifest: Vec<u8>,
+        signature: Option<Vec<u8>>,
+    },
     Closure(ClosureWrap),
 }
 
 impl ClientBuilder {
     pub fn new() -> Self {
-        Self {
-            wraps_to_load: vec![],
-        }
+        Self::default()
     }
 
     pub fn add_file<P: Into<PathBuf>>(mut self, uri: Uri, path: P) -> Self {
@@ -104,26 +851,207 @@ impl ClientBuilder {
         self
     }
 
+    pub fn add_bytes<W: Into<Vec<u8>>, M: Into<Vec<u8>>>(
+        mut self,
+        uri: Uri,
+        wasm: W,
+        manifest: M,
+    ) -> Self {
+        self.wraps_to_load.push((
+            uri,
+            LoadWrapRequest::Bytes {
+                wasm: wasm.into(),
+                manifest: manifest.into(),
+                signature: None,
+            },
+        ));
+        self
+    }
+
+    pub fn add_signed_bytes<W: Into<Vec<u8>>, M: Into<Vec<u8>>, S: Into<Vec<u8>>>(
+        mut self,
+        uri: Uri,
+        wasm: W,
+        manifest: M,
+        signature: S,
+    ) -> Self {
+        self.wraps_to_load.push((
+            uri,
+            LoadWrapRequest::Bytes {
+                wasm: wasm.into(),
+                manifest: manifest.into(),
+                signature: Some(signature.into()),
+            },
+        ));
+        self
+    }
+
+    /// Adds a wrap embedded with [`embed_wrap!`](crate::embed_wrap).
+    pub fn add_embedded(self, uri: Uri, embedded_wrap: EmbeddedWrap) -> Self {
+        match embedded_wrap.signature {
+            Some(signature) => {
+                self.add_signed_bytes(uri, embedded_wrap.wasm, embedded_wrap.manifest, signature)
+            }
+            None => self.add_bytes(uri, embedded_wrap.wasm, embedded_wrap.manifest),
+        }
+    }
+
     pub fn add_closure(mut self, uri: Uri, closure_wrap: ClosureWrap) -> Self {
         self.wraps_to_load
             .push((uri, LoadWrapRequest::Closure(closure_wrap)));
         self
     }
 
+    /// Invocations of `from` are forwarded to `to`. Redirects can be chained.
+    pub fn add_redirect(mut self, from: Uri, to: Uri) -> Self {
+        self.redirects.insert(from, to);
+        self
+    }
+
+    /// Resolvers are consulted in the order they were added, for uris that aren't loaded.
+    pub fn add_resolver<R: UriResolver + 'static>(mut self, resolver: R) -> Self {
+        self.resolvers.push(Box::new(resolver));
+        self
+    }
+
+    /// Every wrap loaded from wasm, up front or lazily, must match the hashes pinned in `lockfile`.
+    /// Closures are not pinned.
+    pub fn with_lockfile(mut self, lockfile: Lockfile) -> Self {
+        self.lockfile = Some(lockfile);
+        self
+    }
+
+    /// Wraps signed by `key` are recorded as such, see [`LoadedWrap::signer`].
+    pub fn add_trusted_key(mut self, key: VerifyingKey) -> Self {
+        self.signature_policy.trusted_keys.push(key);
+        self
+    }
+
+    /// Refuse to load wraps that aren't signed by a trusted key. Closures are exempt.
+    pub fn require_signatures(mut self) -> Self {
+        self.signature_policy.required = true;
+        self
+    }
+
+    /// Handles debug logs of every wrap without a handler of its own. Defaults to [`DiscardDebugLog`].
+    pub fn with_debug_log_handler<H: DebugLogHandler + 'static>(mut self, handler: H) -> Self {
+        self.debug_log_handler = Arc::new(handler);
+        self
+    }
+
+    /// Handles debug logs of the wrap at `uri`, however it's invoked, e.g. through redirects.
+    pub fn add_debug_log_handler<H: DebugLogHandler + 'static>(
+        mut self,
+        uri: Uri,
+        handler: H,
+    ) -> Self {
+        self.debug_log_handlers.insert(uri, Arc::new(handler));
+        self
+    }
+
+    /// Middleware runs around every invocation going through `invoke_raw`, in the order it was added.
+    pub fn add_middleware<M: InvokeMiddleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Memoizes the results of `method` by its args. Only use it for methods whose result depends on
+    /// nothing but their args. Errors are never memoized.
+    pub fn cache_results<M: Into<String>>(
+        mut self,
+        uri: Uri,
+        method: M,
+        config: ResultCacheConfig,
+    ) -> Self {
+        self.result_caches
+            .entry(uri)
+            .or_default()
+            .insert(method.into(), config);
+        self
+    }
+
+    /// Identical concurrent invocations (same uri, method, args and env) share a single execution.
+    /// When that execution fails, every caller sharing it gets an [`InvokeError::Coalesced`].
+    pub fn coalesce_invocations(mut self) -> Self {
+        self.coalesce_invocations = true;
+        self
+    }
+
+    /// Check args against the wrap's ABI before invoking it, and results after, so bad args fail with
+    /// [`InvokeError::InvalidArgs`] rather than inside the wrap. Wraps without a known ABI aren't checked.
+    pub fn validate_against_abi(mut self) -> Self {
+        self.validate = true;
+        self
+    }
+
     pub async fn load(self) -> Result<Client, LoadError> {
+        // Catch redirect cycles early rather than on first invoke
+        for from in self.redirects.keys() {
+            resolve_redirect_chain(&self.redirects, from).map_err(LoadError::RedirectCycle)?;
+        }
+
         let mut loaded_wraps = HashMap::new();
 
         // TODO: load all wraps in parallel
         for (uri, load_wrap_request) in self.wraps_to_load {
             let wrap = match load_wrap_request {
                 LoadWrapRequest::Fs(path) => Wrap::Loaded(LoadedWrap::new_from_file(path).await?),
+                LoadWrapRequest::Bytes {
+                    wasm,
+                    manifest,
+                    signature,
+                } => Wrap::Loaded(
+                    LoadedWrap::new_from_bytes(&wasm, manifest)?.with_signature(signature),
+                ),
                 LoadWrapRequest::Closure(closure_wrap) => Wrap::Closure(closure_wrap),
             };
-            loaded_wraps.insert(uri, wrap);
+            verify_wrap(self.lockfile.as_ref(), &self.signature_policy, &uri, &wrap)?;
+            loaded_wraps.insert(uri, Arc::new(wrap));
         }
 
         Ok(Client {
-            inner: Arc::new(ClientInner { loaded_wraps }),
+            inner: Arc::new(ClientInner {
+                loaded_wraps: RwLock::new(loaded_wraps),
+                redirects: self.redirects,
+                resolved_redirects: RwLock::new(HashMap::new()),
+                resolvers: self.resolvers,
+                lockfile: self.lockfile,
+                signature_policy: self.signature_policy,
+                debug_log_handler: self.debug_log_handler,
+                debug_log_handlers: self.debug_log_handlers,
+                metrics: InvocationMetrics::default(),
+                middleware: self.middleware,
+                result_caches: self
+                    .result_caches
+                    .into_iter()
+                    .map(|(uri, configs)| {
+                        let caches = configs
+                            .into_iter()
+                            .map(|(method, config)| (method, ResultCache::new(config)))
+                            .collect();
+                        (uri, caches)
+                    })
+                    .collect(),
+                single_flight: self.coalesce_invocations.then(SingleFlight::default),
+                validate: self.validate,
+            }),
         })
     }
 }
+
+/// Checks a wasm wrap against the lockfile and signature policy before it can be invoked.
+fn verify_wrap(
+    lockfile: Option<&Lockfile>,
+    signature_policy: &SignaturePolicy,
+    uri: &Uri,
+    wrap: &Wrap,
+) -> Result<(), LoadError> {
+    let Wrap::Loaded(loaded_wrap) = wrap else {
+        return Ok(());
+    };
+
+    if let Some(lockfile) = lockfile {
+        lockfile.verify(uri, &loaded_wrap.hashes)?;
+    }
+    signature_policy.verify(uri, loaded_wrap)
+}
@@ -0,0 +1,23 @@
+use polywrap_uri::Uri;
+use std::collections::HashMap;
+
+/// Follows `redirects` starting from `uri`, returning every uri visited along the way.
+/// The last uri of the chain is the final target.
+///
+/// If the redirects loop back on themselves, the chain (ending with the repeated uri) is returned as an error.
+pub fn resolve_redirect_chain(
+    redirects: &HashMap<Uri, Uri>,
+    uri: &Uri,
+) -> Result<Vec<Uri>, Vec<Uri>> {
+    let mut chain = vec![uri.clone()];
+
+    while let Some(next) = redirects.get(chain.last().unwrap()) {
+        let is_cycle = chain.contains(next);
+        chain.push(next.clone());
+        if is_cycle {
+            return Err(chain);
+        }
+    }
+
+    Ok(chain)
+}
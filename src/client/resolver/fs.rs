@@ -0,0 +1,153 @@
+use super::{UriResolution, UriResolver};
+use crate::{LoadError, LoadedWrap, Wrap};
+use async_trait::async_trait;
+use polywrap_uri::Uri;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{fs, sync::Mutex};
+
+/// Resolves `wrap://fs/<path>` and `wrap://file/<path>` uris by loading the wrap directory at `<path>`.
+#[derive(Default)]
+pub struct FsResolver {
+    /// If set, only wraps inside one of these directories can be loaded.
+    allowed_roots: Option<Vec<PathBuf>>,
+    /// Wraps already loaded, keyed by canonical path so different uris to the same directory share a wrap.
+    cache: Mutex<HashMap<PathBuf, Arc<Wrap>>>,
+}
+
+impl FsResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restrict loading to wraps inside `root`. Can be called multiple times to allow several roots.
+    pub fn allow_root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.allowed_roots
+            .get_or_insert_with(Vec::new)
+            .push(root.into());
+        self
+    }
+
+    async fn is_allowed(&self, path: &Path) -> bool {
+        let Some(allowed_roots) = &self.allowed_roots else {
+            return true;
+        };
+
+        for root in allowed_roots {
+            if let Ok(root) = fs::canonicalize(root).await {
+                if path.starts_with(root) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl UriResolver for FsResolver {
+    async fn resolve(&self, uri: &Uri) -> Result<UriResolution, LoadError> {
+        if !matches!(uri.authority(), "fs" | "file") {
+            return Ok(UriResolution::NotFound);
+        }
+
+        let path = PathBuf::from(uri.path());
+        let path = fs::canonicalize(&path)
+            .await
+            .map_err(|_| LoadError::WrapNotFound(path))?;
+
+        if !self.is_allowed(&path).await {
+            return Err(LoadError::PathNotAllowed(path));
+        }
+
+        if let Some(wrap) = self.cache.lock().await.get(&path) {
+            return Ok(UriResolution::Wrap(wrap.clone()));
+        }
+
+        // Load without holding the lock so a slow load doesn't block other resolutions. If the same wrap
+        // was loaded concurrently, the first one cached wins so every caller shares it.
+        let wrap = Arc::new(Wrap::Loaded(LoadedWrap::new_from_file(path.clone()).await?));
+        let wrap = self.cache.lock().await.entry(path).or_insert(wrap).clone();
+
+        Ok(UriResolution::Wrap(wrap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `<tmp>/<test>/allowed/wrap` and `<tmp>/<test>/outside/wrap`, both holding the test wrap.
+    fn roots(test: &str) -> PathBuf {
+        let base =
+            std::env::temp_dir().join(format!("fs-resolver-{}-{}", test, std::process::id()));
+        std::fs::remove_dir_all(&base).ok();
+        for root in ["allowed", "outside"] {
+            let dir = base.join(root).join("wrap");
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in ["wrap.wasm", "wrap.info"] {
+                std::fs::copy(format!("assets/test-wrap/{}", file), dir.join(file)).unwrap();
+            }
+        }
+        base
+    }
+
+    async fn resolve(base: &Path, path: &str) -> Result<UriResolution, LoadError> {
+        let resolver = FsResolver::new().allow_root(base.join("allowed"));
+        let uri = Uri::try_from(format!("wrap://fs/{}/{}", base.display(), path)).unwrap();
+        resolver.resolve(&uri).await
+    }
+
+    #[tokio::test]
+    async fn loads_wraps_inside_allowed_roots() {
+        let base = roots("inside");
+
+        assert!(matches!(
+            resolve(&base, "allowed/wrap").await,
+            Ok(UriResolution::Wrap(_))
+        ));
+
+        std::fs::remove_dir_all(&base).ok();
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_outside_allowed_roots() {
+        let base = roots("outside");
+
+        assert!(matches!(
+            resolve(&base, "outside/wrap").await,
+            Err(LoadError::PathNotAllowed(_))
+        ));
+
+        std::fs::remove_dir_all(&base).ok();
+    }
+
+    #[tokio::test]
+    async fn rejects_parent_dir_escapes() {
+        let base = roots("parent");
+
+        assert!(matches!(
+            resolve(&base, "allowed/../outside/wrap").await,
+            Err(LoadError::PathNotAllowed(_))
+        ));
+
+        std::fs::remove_dir_all(&base).ok();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_symlink_escapes() {
+        let base = roots("symlink");
+        std::os::unix::fs::symlink(base.join("outside/wrap"), base.join("allowed/link")).unwrap();
+
+        assert!(matches!(
+            resolve(&base, "allowed/link").await,
+            Err(LoadError::PathNotAllowed(_))
+        ));
+
+        std::fs::remove_dir_all(&base).ok();
+    }
+}
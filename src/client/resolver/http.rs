@@ -0,0 +1,173 @@
+use super::{UriResolution, UriResolver};
+use crate::{LoadError, LoadedWrap, Wrap};
+use async_trait::async_trait;
+use polywrap_uri::Uri;
+use reqwest::StatusCode;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, sync::Mutex};
+
+/// Largest file we are willing to download.
+const MAX_RESPONSE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Resolves `wrap://http/<host>/<path>` and `wrap://https/<host>/<path>` uris by fetching
+/// `wrap.wasm` and `wrap.info` from `http(s)://<host>/<path>/`.
+#[derive(Default)]
+pub struct HttpResolver {
+    client: reqwest::Client,
+    /// If set, downloaded wraps are stored here and reused across runs.
+    cache_dir: Option<PathBuf>,
+    /// Wraps already loaded, keyed by their base url.
+    cache: Mutex<HashMap<String, Arc<Wrap>>>,
+}
+
+impl HttpResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cache_dir<P: Into<PathBuf>>(mut self, cache_dir: P) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>, LoadError> {
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(LoadError::Http)?;
+        let too_large = || LoadError::ResponseTooLarge(url.to_string());
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_RESPONSE_SIZE)
+        {
+            return Err(too_large());
+        }
+
+        // The content length may be missing or wrong, so the limit is enforced while reading too
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(LoadError::Http)? {
+            if (bytes.len() + chunk.len()) as u64 > MAX_RESPONSE_SIZE {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+
+    /// Reads the file from the disk cache, or fetches it and writes it to the disk cache.
+    async fn fetch_cached(
+        &self,
+        base_url: &str,
+        cache_path: Option<&PathBuf>,
+        file: &str,
+    ) -> Result<Vec<u8>, LoadError> {
+        let Some(cache_path) = cache_path else {
+            return self.fetch(&format!("{}/{}", base_url, file)).await;
+        };
+
+        let cache_path = cache_path.join(file);
+        if let Ok(bytes) = fs::read(&cache_path).await {
+            return Ok(bytes);
+        }
+
+        let bytes = self.fetch(&format!("{}/{}", base_url, file)).await?;
+
+        // Write to a temporary file first so a crash never leaves a truncated file in the cache
+        let tmp_path = cache_path.with_file_name(tmp_file_name(file));
+        fs::write(&tmp_path, &bytes)
+            .await
+            .map_err(|_| LoadError::CacheWriteFailed(tmp_path.clone()))?;
+        fs::rename(&tmp_path, &cache_path)
+            .await
+            .map_err(|_| LoadError::CacheWriteFailed(cache_path))?;
+
+        Ok(bytes)
+    }
+}
+
+/// `<file>.<pid>.<nonce>.tmp`, unique across processes and concurrent downloads sharing a cache directory.
+fn tmp_file_name(file: &str) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos() as u64);
+    let nonce = nanos ^ (COUNTER.fetch_add(1, Ordering::Relaxed) << 32);
+    format!("{}.{}.{:x}.tmp", file, std::process::id(), nonce)
+}
+
+#[async_trait]
+impl UriResolver for HttpResolver {
+    async fn resolve(&self, uri: &Uri) -> Result<UriResolution, LoadError> {
+        let scheme = uri.authority();
+        if !matches!(scheme, "http" | "https") {
+            return Ok(UriResolution::NotFound);
+        }
+
+        let path = uri.path().trim_end_matches('/');
+        let base_url = format!("{}://{}", scheme, path);
+
+        if let Some(wrap) = self.cache.lock().await.get(&base_url) {
+            return Ok(UriResolution::Wrap(wrap.clone()));
+        }
+
+        let cache_path = match &self.cache_dir {
+            Some(cache_dir) => {
+                // Strip anything that could escape the cache directory
+                let cache_path = path
+                    .split('/')
+                    .filter(|segment| !matches!(*segment, "" | "." | ".."))
+                    .fold(cache_dir.join(scheme), |cache_path, segment| {
+                        cache_path.join(segment.replace(':', "_"))
+                    });
+                fs::create_dir_all(&cache_path)
+                    .await
+                    .map_err(|_| LoadError::CacheWriteFailed(cache_path.clone()))?;
+                Some(cache_path)
+            }
+            None => None,
+        };
+
+        let wasm = self
+            .fetch_cached(&base_url, cache_path.as_ref(), "wrap.wasm")
+            .await?;
+        let manifest = self
+            .fetch_cached(&base_url, cache_path.as_ref(), "wrap.info")
+            .await?;
+
+        // Signatures are optional, their absence is only a problem if the client requires them
+        let signature = match self
+            .fetch_cached(&base_url, cache_path.as_ref(), "wrap.sig")
+            .await
+        {
+            Ok(signature) => Some(signature),
+            Err(LoadError::Http(error)) if error.status() == Some(StatusCode::NOT_FOUND) => None,
+            Err(error) => return Err(error),
+        };
+
+        // Fetched without holding the lock so a slow download doesn't block other resolutions. If the same
+        // wrap was fetched concurrently, the first one cached wins so every caller shares it.
+        let wrap = Arc::new(Wrap::Loaded(
+            LoadedWrap::new_from_bytes(&wasm, manifest)?.with_signature(signature),
+        ));
+        let wrap = self
+            .cache
+            .lock()
+            .await
+            .entry(base_url)
+            .or_insert(wrap)
+            .clone();
+
+        Ok(UriResolution::Wrap(wrap))
+    }
+}
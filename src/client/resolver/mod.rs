@@ -0,0 +1,55 @@
+use crate::{LoadError, Wrap};
+pub use async_trait::async_trait;
+use polywrap_uri::Uri;
+use std::sync::Arc;
+
+mod fs;
+pub use fs::*;
+mod http;
+pub use http::*;
+mod registry;
+pub use registry::*;
+
+pub enum UriResolution {
+    /// The uri resolved to a wrap that is ready to be invoked
+    Wrap(Arc<Wrap>),
+    /// The uri should be resolved as another uri instead
+    Redirect(Uri),
+    /// This resolver doesn't know about the uri, the next resolver in the chain should be tried
+    NotFound,
+}
+
+/// Resolves uris that aren't loaded yet. Resolvers are consulted lazily, the first time a uri is invoked.
+#[async_trait]
+pub trait UriResolver: Send + Sync {
+    async fn resolve(&self, uri: &Uri) -> Result<UriResolution, LoadError>;
+}
+
+/// Tries each resolver in order, returning the first resolution that isn't `NotFound`.
+#[derive(Default)]
+pub struct ResolverChain {
+    resolvers: Vec<Box<dyn UriResolver>>,
+}
+
+impl ResolverChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, resolver: Box<dyn UriResolver>) {
+        self.resolvers.push(resolver);
+    }
+}
+
+#[async_trait]
+impl UriResolver for ResolverChain {
+    async fn resolve(&self, uri: &Uri) -> Result<UriResolution, LoadError> {
+        for resolver in &self.resolvers {
+            match resolver.resolve(uri).await? {
+                UriResolution::NotFound => continue,
+                resolution => return Ok(resolution),
+            }
+        }
+        Ok(UriResolution::NotFound)
+    }
+}
@@ -0,0 +1,283 @@
+use super::{UriResolution, UriResolver};
+use crate::{LoadError, LoadedWrap, Wrap};
+use async_trait::async_trait;
+use polywrap_uri::Uri;
+use semver::{Version, VersionReq};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{fs, sync::Mutex};
+
+/// Resolves `wrap://registry/<name>@<version range>` uris from a local registry directory or index file.
+///
+/// A registry directory is laid out as `<root>/<name>/<version>/`, each version directory holding a `wrap.wasm`
+/// and `wrap.info`. Publishing a wrap is just dropping a new version directory in. The highest version matching
+/// the requested range is loaded, and omitting the range picks the latest version.
+pub struct RegistryResolver {
+    source: Source,
+    /// Wraps already loaded, keyed by version directory.
+    cache: Mutex<HashMap<PathBuf, Arc<Wrap>>>,
+}
+
+enum Source {
+    Directory(PathBuf),
+    /// A JSON file mapping names to versions to wrap directories, e.g.
+    /// `{ "@team/wrap": { "1.2.0": "wraps/wrap-1.2.0" } }`
+    Index(PathBuf),
+}
+
+impl RegistryResolver {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            source: Source::Directory(root.into()),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolves names through an index file instead of a directory layout. Relative wrap directories are relative
+    /// to the index. The index is read on every resolution, so wraps are published by editing it.
+    pub fn from_index<P: Into<PathBuf>>(index: P) -> Self {
+        Self {
+            source: Source::Index(index.into()),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Finds the highest published version of `name` matching `version_req`.
+    async fn find_version(
+        &self,
+        name: &str,
+        version_req: &VersionReq,
+    ) -> Result<Option<PathBuf>, LoadError> {
+        // Names may be scoped (`team/wrap`), but must stay inside the registry
+        if name
+            .split('/')
+            .any(|segment| matches!(segment, "" | "." | ".."))
+        {
+            return Err(LoadError::InvalidRegistryUri(name.to_string()));
+        }
+
+        let versions = match &self.source {
+            Source::Directory(root) => directory_versions(&root.join(name)).await,
+            Source::Index(index) => index_versions(index, name).await?,
+        };
+
+        Ok(versions
+            .into_iter()
+            .filter(|(version, _)| version_req.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, path)| path))
+    }
+}
+
+/// The version directories of a package, ignoring anything that isn't named after a version.
+async fn directory_versions(package_dir: &Path) -> Vec<(Version, PathBuf)> {
+    let Ok(mut entries) = fs::read_dir(package_dir).await else {
+        return vec![];
+    };
+
+    let mut versions = vec![];
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if let Some(version) = entry
+            .file_name()
+            .to_str()
+            .and_then(|version| Version::parse(version).ok())
+        {
+            versions.push((version, entry.path()));
+        }
+    }
+    versions
+}
+
+async fn index_versions(index: &Path, name: &str) -> Result<Vec<(Version, PathBuf)>, LoadError> {
+    let bytes = fs::read(index)
+        .await
+        .map_err(|_| LoadError::RegistryIndexNotFound(index.to_path_buf()))?;
+    let mut packages: HashMap<String, HashMap<String, PathBuf>> = serde_json::from_slice(&bytes)
+        .map_err(|e| LoadError::InvalidRegistryIndex(e.to_string()))?;
+    let Some(versions) = packages.remove(name) else {
+        return Ok(vec![]);
+    };
+
+    let index_dir = index.parent().unwrap_or(Path::new(""));
+    versions
+        .into_iter()
+        .map(|(version, path)| {
+            let version = Version::parse(&version).map_err(|e| {
+                LoadError::InvalidRegistryIndex(format!("{} {}: {}", name, version, e))
+            })?;
+            Ok((version, index_dir.join(path)))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl UriResolver for RegistryResolver {
+    async fn resolve(&self, uri: &Uri) -> Result<UriResolution, LoadError> {
+        if uri.authority() != "registry" {
+            return Ok(UriResolution::NotFound);
+        }
+
+        // A leading `@` is part of a scoped name (`@team/wrap`), not a version range
+        let path = uri.path();
+        let (name, version_req) = match path.rfind('@').filter(|&at| at > 0) {
+            Some(at) => (
+                &path[..at],
+                VersionReq::parse(&path[at + 1..])
+                    .map_err(|_| LoadError::InvalidRegistryUri(path.to_string()))?,
+            ),
+            None => (path, VersionReq::STAR),
+        };
+
+        let Some(path) = self.find_version(name, &version_req).await? else {
+            return Ok(UriResolution::NotFound);
+        };
+
+        let mut cache = self.cache.lock().await;
+        if let Some(wrap) = cache.get(&path) {
+            return Ok(UriResolution::Wrap(wrap.clone()));
+        }
+
+        let wrap = Arc::new(Wrap::Loaded(LoadedWrap::new_from_file(path.clone()).await?));
+        cache.insert(path, wrap.clone());
+
+        Ok(UriResolution::Wrap(wrap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A registry in a fresh temporary directory, publishing the test wrap under `name` at each of `versions`.
+    fn registry(test: &str, name: &str, versions: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("registry-{}-{}", test, std::process::id()));
+        std::fs::remove_dir_all(&root).ok();
+        for version in versions {
+            let dir = root.join(name).join(version);
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in ["wrap.wasm", "wrap.info"] {
+                std::fs::copy(format!("assets/test-wrap/{}", file), dir.join(file)).unwrap();
+            }
+        }
+        root
+    }
+
+    async fn resolve(resolver: &RegistryResolver, uri: &str) -> Result<UriResolution, LoadError> {
+        resolver
+            .resolve(&Uri::try_from(format!("wrap://registry/{}", uri)).unwrap())
+            .await
+    }
+
+    #[tokio::test]
+    async fn picks_highest_matching_version() {
+        let root = registry("versions", "wrap", &["1.0.0", "1.2.0", "2.0.0"]);
+        let resolver = RegistryResolver::new(&root);
+
+        let version = |req: &str| {
+            let req = VersionReq::parse(req).unwrap();
+            let resolver = &resolver;
+            async move { resolver.find_version("wrap", &req).await.unwrap() }
+        };
+        assert_eq!(version("^1").await, Some(root.join("wrap/1.2.0")));
+        assert_eq!(version("~1.0").await, Some(root.join("wrap/1.0.0")));
+        assert_eq!(version("*").await, Some(root.join("wrap/2.0.0")));
+        assert_eq!(version("^3").await, None);
+
+        assert!(matches!(
+            resolve(&resolver, "wrap@^1").await,
+            Ok(UriResolution::Wrap(_))
+        ));
+        assert!(matches!(
+            resolve(&resolver, "wrap@^3").await,
+            Ok(UriResolution::NotFound)
+        ));
+        assert!(matches!(
+            resolve(&resolver, "wrap@latest").await,
+            Err(LoadError::InvalidRegistryUri(_))
+        ));
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[tokio::test]
+    async fn scoped_names() {
+        let root = registry("scoped", "@team/wrap", &["1.0.0"]);
+        let resolver = RegistryResolver::new(&root);
+
+        for uri in ["@team/wrap", "@team/wrap@^1", "@team/wrap@1.0.0"] {
+            assert!(
+                matches!(resolve(&resolver, uri).await, Ok(UriResolution::Wrap(_))),
+                "{}",
+                uri
+            );
+        }
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[tokio::test]
+    async fn index_file() {
+        let root = registry("index", "wraps", &["wrap-1.0.0", "wrap-1.2.0"]);
+        let index = root.join("index.json");
+        std::fs::write(
+            &index,
+            r#"{ "@team/wrap": { "1.0.0": "wraps/wrap-1.0.0", "1.2.0": "wraps/wrap-1.2.0" } }"#,
+        )
+        .unwrap();
+        let resolver = RegistryResolver::from_index(&index);
+
+        let version = |req: &str| {
+            let req = VersionReq::parse(req).unwrap();
+            let resolver = &resolver;
+            async move { resolver.find_version("@team/wrap", &req).await.unwrap() }
+        };
+        assert_eq!(version("*").await, Some(root.join("wraps/wrap-1.2.0")));
+        assert_eq!(version("~1.0").await, Some(root.join("wraps/wrap-1.0.0")));
+
+        assert!(matches!(
+            resolve(&resolver, "@team/wrap@^1").await,
+            Ok(UriResolution::Wrap(_))
+        ));
+        assert!(matches!(
+            resolve(&resolver, "other").await,
+            Ok(UriResolution::NotFound)
+        ));
+
+        std::fs::write(
+            &index,
+            r#"{ "@team/wrap": { "latest": "wraps/wrap-1.2.0" } }"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            resolve(&resolver, "@team/wrap").await,
+            Err(LoadError::InvalidRegistryIndex(_))
+        ));
+
+        let missing = RegistryResolver::from_index(root.join("missing.json"));
+        assert!(matches!(
+            resolve(&missing, "@team/wrap").await,
+            Err(LoadError::RegistryIndexNotFound(_))
+        ));
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[tokio::test]
+    async fn names_stay_inside_the_registry() {
+        let resolver = RegistryResolver::new(std::env::temp_dir());
+
+        for uri in ["../wrap", "team//wrap", "./wrap@1"] {
+            assert!(
+                matches!(
+                    resolve(&resolver, uri).await,
+                    Err(LoadError::InvalidRegistryUri(_))
+                ),
+                "{}",
+                uri
+            );
+        }
+    }
+}
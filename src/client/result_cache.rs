@@ -0,0 +1,273 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How results of a pure method are memoized, see [`ClientBuilder::cache_results`](crate::ClientBuilder::cache_results).
+#[derive(Clone, Debug)]
+pub struct ResultCacheConfig {
+    /// Least recently used results are evicted past this many entries
+    pub max_entries: usize,
+    /// Results older than this are invoked again
+    pub ttl: Option<Duration>,
+}
+
+impl ResultCacheConfig {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            ttl: None,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResultCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay under `max_entries`, expired entries aren't counted
+    pub evictions: u64,
+    pub entries: usize,
+}
+
+/// Memoized results of one method, keyed by the msgpack encoded args.
+pub(crate) struct ResultCache {
+    config: ResultCacheConfig,
+    state: Mutex<ResultCacheState>,
+}
+
+#[derive(Default)]
+struct ResultCacheState {
+    entries: HashMap<Vec<u8>, CachedResult>,
+    /// Keys by last use, oldest first
+    recency: BTreeMap<u64, Vec<u8>>,
+    /// Keys by insertion, oldest first. Every entry has the same ttl, so this is the order they expire in.
+    insertions: BTreeMap<u64, Vec<u8>>,
+    tick: u64,
+    stats: ResultCacheStats,
+}
+
+struct CachedResult {
+    result: Vec<u8>,
+    inserted: Instant,
+    /// Tick of the insertion, its key in `insertions`
+    insertion: u64,
+    last_used: u64,
+}
+
+impl ResultCacheState {
+    fn remove(&mut self, args: &[u8]) -> Option<CachedResult> {
+        let cached = self.entries.remove(args)?;
+        self.recency.remove(&cached.last_used);
+        self.insertions.remove(&cached.insertion);
+        Some(cached)
+    }
+}
+
+impl ResultCache {
+    pub fn new(config: ResultCacheConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(ResultCacheState::default()),
+        }
+    }
+
+    pub fn get(&self, args: &[u8]) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        let expired = match state.entries.get(args) {
+            Some(cached) => self
+                .config
+                .ttl
+                .is_some_and(|ttl| cached.inserted.elapsed() > ttl),
+            None => {
+                state.stats.misses += 1;
+                return None;
+            }
+        };
+        if expired {
+            state.remove(args);
+            state.stats.misses += 1;
+            return None;
+        }
+
+        state.tick += 1;
+        let cached = state.entries.get_mut(args).unwrap();
+        let key = state.recency.remove(&cached.last_used).unwrap();
+        cached.last_used = state.tick;
+        state.recency.insert(state.tick, key);
+        state.stats.hits += 1;
+
+        Some(cached.result.clone())
+    }
+
+    pub fn insert(&self, args: Vec<u8>, result: Vec<u8>) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        state.tick += 1;
+        state.remove(&args);
+        // Expired entries go first, so they never push out results that are still fresh
+        if state.entries.len() >= self.config.max_entries {
+            self.purge_expired(state);
+        }
+        while state.entries.len() >= self.config.max_entries {
+            let Some(oldest) = state.recency.values().next().cloned() else {
+                break;
+            };
+            state.remove(&oldest);
+            state.stats.evictions += 1;
+        }
+
+        state.recency.insert(state.tick, args.clone());
+        state.insertions.insert(state.tick, args.clone());
+        state.entries.insert(
+            args,
+            CachedResult {
+                result,
+                inserted: Instant::now(),
+                insertion: state.tick,
+                last_used: state.tick,
+            },
+        );
+    }
+
+    /// Only looks at the entries that expired, and the next one to expire.
+    fn purge_expired(&self, state: &mut ResultCacheState) {
+        let Some(ttl) = self.config.ttl else {
+            return;
+        };
+        while let Some(oldest) = state.insertions.values().next() {
+            if state.entries[oldest].inserted.elapsed() <= ttl {
+                break;
+            }
+            let oldest = oldest.clone();
+            state.remove(&oldest);
+        }
+    }
+
+    pub fn stats(&self) -> ResultCacheStats {
+        let state = self.state.lock().unwrap();
+        ResultCacheStats {
+            entries: state.entries.len(),
+            ..state.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_millis(50);
+
+    fn cache(max_entries: usize) -> ResultCache {
+        ResultCache::new(ResultCacheConfig::new(max_entries))
+    }
+
+    fn insert(cache: &ResultCache, key: &str) {
+        cache.insert(key.as_bytes().to_vec(), key.to_uppercase().into_bytes());
+    }
+
+    fn get(cache: &ResultCache, key: &str) -> Option<String> {
+        cache
+            .get(key.as_bytes())
+            .map(|result| String::from_utf8(result).unwrap())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = cache(2);
+        insert(&cache, "a");
+        insert(&cache, "b");
+        assert_eq!(get(&cache, "a").as_deref(), Some("A"));
+
+        // `b` wasn't used since `a` was
+        insert(&cache, "c");
+
+        assert_eq!(get(&cache, "b"), None);
+        assert_eq!(get(&cache, "a").as_deref(), Some("A"));
+        assert_eq!(get(&cache, "c").as_deref(), Some("C"));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn reinserting_refreshes_recency() {
+        let cache = cache(2);
+        insert(&cache, "a");
+        insert(&cache, "b");
+        insert(&cache, "a");
+        insert(&cache, "c");
+
+        assert_eq!(get(&cache, "a").as_deref(), Some("A"));
+        assert_eq!(get(&cache, "b"), None);
+    }
+
+    #[test]
+    fn expires_after_ttl() {
+        let cache = ResultCache::new(ResultCacheConfig::new(2).with_ttl(TTL));
+        insert(&cache, "a");
+        assert_eq!(get(&cache, "a").as_deref(), Some("A"));
+
+        std::thread::sleep(TTL * 2);
+
+        assert_eq!(get(&cache, "a"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn expired_entries_make_room_before_fresh_ones_are_evicted() {
+        let cache = ResultCache::new(ResultCacheConfig::new(2).with_ttl(TTL));
+        insert(&cache, "a");
+        std::thread::sleep(TTL * 2);
+        insert(&cache, "b");
+
+        insert(&cache, "c");
+
+        assert_eq!(get(&cache, "b").as_deref(), Some("B"));
+        assert_eq!(get(&cache, "c").as_deref(), Some("C"));
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.evictions, 0);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = cache(2);
+        assert_eq!(get(&cache, "a"), None);
+        insert(&cache, "a");
+        get(&cache, "a");
+        get(&cache, "a");
+        get(&cache, "b");
+
+        assert_eq!(
+            cache.stats(),
+            ResultCacheStats {
+                hits: 2,
+                misses: 2,
+                evictions: 0,
+                entries: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn zero_entries_caches_nothing() {
+        let cache = cache(0);
+        insert(&cache, "a");
+
+        assert_eq!(get(&cache, "a"), None);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
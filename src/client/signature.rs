@@ -0,0 +1,108 @@
+use crate::{LoadError, LoadedWrap, WrapHashes};
+use ed25519_dalek::{Signature, Signer, Verifier};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+use polywrap_uri::Uri;
+
+/// Which publisher keys are trusted, and whether wraps must be signed by one of them to be loaded.
+#[derive(Default)]
+pub struct SignaturePolicy {
+    pub trusted_keys: Vec<VerifyingKey>,
+    /// If false, signatures are still checked to record the signer, but unsigned wraps are allowed.
+    pub required: bool,
+}
+
+impl SignaturePolicy {
+    /// Checks the wrap's detached signature against the trusted keys, recording which key signed it.
+    ///
+    /// The check always runs, since wraps may be shared by clients trusting different keys.
+    pub fn verify(&self, uri: &Uri, loaded_wrap: &LoadedWrap) -> Result<(), LoadError> {
+        let Some(signature) = &loaded_wrap.signature else {
+            return match self.required {
+                true => Err(LoadError::Unsigned(uri.clone())),
+                false => Ok(()),
+            };
+        };
+
+        let signer = Signature::from_slice(signature).ok().and_then(|signature| {
+            let message = signing_message(&loaded_wrap.hashes);
+            self.trusted_keys
+                .iter()
+                .find(|key| key.verify(&message, &signature).is_ok())
+        });
+
+        match signer {
+            Some(signer) => {
+                let _ = loaded_wrap.signer.set(*signer);
+                Ok(())
+            }
+            None if self.required => Err(LoadError::InvalidSignature(uri.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Produces the detached signature (`wrap.sig`) a publisher ships alongside `wrap.wasm` and `wrap.info`.
+pub fn sign_wrap(key: &SigningKey, hashes: &WrapHashes) -> Vec<u8> {
+    key.sign(&signing_message(hashes)).to_bytes().to_vec()
+}
+
+/// Signing the hashes rather than the files themselves binds the signature to both files at once.
+fn signing_message(hashes: &WrapHashes) -> Vec<u8> {
+    format!("{}\n{}", hashes.wasm, hashes.manifest).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri() -> Uri {
+        Uri::try_from("wrap://ens/signed.eth".to_string()).unwrap()
+    }
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    fn signed_wrap(key: &SigningKey) -> LoadedWrap {
+        let wasm = std::fs::read("assets/test-wrap/wrap.wasm").unwrap();
+        let manifest = std::fs::read("assets/test-wrap/wrap.info").unwrap();
+        let loaded_wrap = LoadedWrap::new_from_bytes(&wasm, manifest).unwrap();
+        let signature = sign_wrap(key, &loaded_wrap.hashes);
+        loaded_wrap.with_signature(Some(signature))
+    }
+
+    fn policy(trusted: &SigningKey) -> SignaturePolicy {
+        SignaturePolicy {
+            trusted_keys: vec![trusted.verifying_key()],
+            required: true,
+        }
+    }
+
+    #[test]
+    fn recorded_signer_doesnt_satisfy_other_policies() {
+        let publisher = key(1);
+        let loaded_wrap = signed_wrap(&publisher);
+
+        policy(&publisher).verify(&uri(), &loaded_wrap).unwrap();
+        assert_eq!(loaded_wrap.signer(), Some(&publisher.verifying_key()));
+
+        // Another client sharing the wrap doesn't trust its publisher
+        let result = policy(&key(2)).verify(&uri(), &loaded_wrap);
+        assert!(matches!(result, Err(LoadError::InvalidSignature(_))));
+    }
+
+    #[test]
+    fn unsigned_wrap_is_rejected_only_if_required() {
+        let loaded_wrap = signed_wrap(&key(1)).with_signature(None);
+
+        let result = policy(&key(1)).verify(&uri(), &loaded_wrap);
+        assert!(matches!(result, Err(LoadError::Unsigned(_))));
+
+        let optional = SignaturePolicy {
+            required: false,
+            ..policy(&key(1))
+        };
+        assert!(optional.verify(&uri(), &loaded_wrap).is_ok());
+        assert_eq!(loaded_wrap.signer(), None);
+    }
+}
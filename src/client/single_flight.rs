@@ -0,0 +1,189 @@
+use crate::InvokeError;
+use polywrap_uri::Uri;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+type SharedResult = Result<Vec<u8>, Arc<InvokeError>>;
+
+/// Uri, method, args and env of an invocation.
+type FlightKey = (Uri, String, Vec<u8>, Vec<u8>);
+
+/// Identical invocations in flight, so concurrent callers can share a single execution.
+#[derive(Default)]
+pub(crate) struct SingleFlight {
+    in_flight: Mutex<HashMap<FlightKey, broadcast::Sender<SharedResult>>>,
+}
+
+pub(crate) enum Flight<'a> {
+    /// No identical invocation is in flight, the caller must execute it and [`FlightGuard::finish`] it
+    Leader(FlightGuard<'a>),
+    /// An identical invocation is in flight, its result will be broadcast
+    Follower(broadcast::Receiver<SharedResult>),
+}
+
+impl SingleFlight {
+    pub fn join(&self, uri: &Uri, method: &str, args: &[u8], env: &[u8]) -> Flight<'_> {
+        let key = (uri.clone(), method.to_string(), args.to_vec(), env.to_vec());
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(sender) = in_flight.get(&key) {
+            return Flight::Follower(sender.subscribe());
+        }
+
+        let (sender, _) = broadcast::channel(1);
+        in_flight.insert(key.clone(), sender);
+        Flight::Leader(FlightGuard {
+            single_flight: self,
+            key: Some(key),
+        })
+    }
+}
+
+/// Held by the caller executing an invocation. If dropped before finishing, followers execute on their own.
+pub(crate) struct FlightGuard<'a> {
+    single_flight: &'a SingleFlight,
+    key: Option<FlightKey>,
+}
+
+impl FlightGuard<'_> {
+    /// Broadcasts the result to the followers, which report errors as [`InvokeError::Coalesced`] around the
+    /// same error. The leader gets its result back unchanged.
+    // Passes through the result of `invoke_raw`, which has the same error type
+    #[allow(clippy::result_large_err)]
+    pub fn finish(mut self, result: Result<Vec<u8>, InvokeError>) -> Result<Vec<u8>, InvokeError> {
+        let key = self.key.take().unwrap();
+        let sender = self.single_flight.in_flight.lock().unwrap().remove(&key);
+
+        if let Some(sender) = sender.filter(|sender| sender.receiver_count() > 0) {
+            let shared = match &result {
+                Ok(result) => Ok(result.clone()),
+                Err(error) => Err(Arc::new(error.clone())),
+            };
+            let _ = sender.send(shared);
+        }
+        result
+    }
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.single_flight.in_flight.lock().unwrap().remove(&key);
+        }
+    }
+}
+
+pub(crate) fn coalesced(source: Arc<InvokeError>) -> InvokeError {
+    InvokeError::Coalesced {
+        uri: source.uri().clone(),
+        method: source.method().to_string(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uri() -> Uri {
+        Uri::try_from("wrap://ens/a.eth".to_string()).unwrap()
+    }
+
+    fn join(single_flight: &SingleFlight) -> Flight<'_> {
+        single_flight.join(&uri(), "run", b"args", b"")
+    }
+
+    fn not_found() -> InvokeError {
+        InvokeError::MethodNotFound {
+            uri: uri(),
+            method: "run".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn leader_keeps_original_error_and_followers_share_it() {
+        let single_flight = SingleFlight::default();
+        let Flight::Leader(leader) = join(&single_flight) else {
+            panic!("first invocation should lead");
+        };
+        let Flight::Follower(mut follower) = join(&single_flight) else {
+            panic!("identical invocation should follow");
+        };
+
+        let result = leader.finish(Err(not_found()));
+
+        assert!(matches!(result, Err(InvokeError::MethodNotFound { .. })));
+        let shared = follower.recv().await.unwrap().map_err(coalesced);
+        match shared {
+            Err(InvokeError::Coalesced { source, .. }) => {
+                assert!(matches!(*source, InvokeError::MethodNotFound { .. }))
+            }
+            _ => panic!("follower should get a coalesced error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn followers_see_the_variant_of_the_error() {
+        let single_flight = SingleFlight::default();
+        let Flight::Leader(leader) = join(&single_flight) else {
+            panic!("first invocation should lead");
+        };
+        let Flight::Follower(mut follower) = join(&single_flight) else {
+            panic!("identical invocation should follow");
+        };
+
+        let invalid_args = InvokeError::InvalidArgs {
+            uri: uri(),
+            method: "run".to_string(),
+            path: "items[1]".to_string(),
+            expected: "UInt8!".to_string(),
+            found: "string".to_string(),
+        };
+        let _ = leader.finish(Err(invalid_args));
+
+        match follower.recv().await.unwrap().map_err(coalesced) {
+            Err(InvokeError::Coalesced { source, .. }) => match source.as_ref() {
+                InvokeError::InvalidArgs { path, .. } => assert_eq!(path, "items[1]"),
+                error => panic!("follower got {:?}", error),
+            },
+            _ => panic!("follower should get a coalesced error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn followers_share_results() {
+        let single_flight = SingleFlight::default();
+        let Flight::Leader(leader) = join(&single_flight) else {
+            panic!("first invocation should lead");
+        };
+        let Flight::Follower(mut follower) = join(&single_flight) else {
+            panic!("identical invocation should follow");
+        };
+
+        assert_eq!(leader.finish(Ok(vec![1])).unwrap(), vec![1]);
+        assert_eq!(follower.recv().await.unwrap().unwrap(), vec![1]);
+        // The flight is over, the next invocation leads again
+        assert!(matches!(join(&single_flight), Flight::Leader(_)));
+    }
+
+    #[test]
+    fn dropped_leader_lets_followers_retry() {
+        let single_flight = SingleFlight::default();
+        let Flight::Leader(leader) = join(&single_flight) else {
+            panic!("first invocation should lead");
+        };
+        let Flight::Follower(mut follower) = join(&single_flight) else {
+            panic!("identical invocation should follow");
+        };
+
+        drop(leader);
+
+        assert!(matches!(
+            follower.try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+    }
+}
@@ -0,0 +1,499 @@
+use super::*;
+use crate::uri;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+fn echo() -> ClosureWrap {
+    ClosureWrap::new().add_method("echo", |args: &String| Ok::<_, String>(args.clone()))
+}
+
+/// Counts the invocations it sees.
+#[derive(Clone, Default)]
+struct Counter(Arc<AtomicUsize>);
+
+#[async_trait]
+impl InvokeMiddleware for Counter {
+    async fn before(&self, _request: &mut InvokeRequest) -> Result<(), InvokeError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn subinvocations_go_through_redirects_and_middleware() {
+    let counter = Counter::default();
+    let client = ClientBuilder::new()
+        .add_closure(uri!("ens/callee.eth"), echo())
+        .add_redirect(uri!("ens/alias.eth"), uri!("ens/callee.eth"))
+        .add_middleware(counter.clone())
+        .load()
+        .await
+        .unwrap();
+
+    let args = to_vec(&"hi".to_string()).unwrap();
+    let result = (client.subinvoker())(&uri!("ens/alias.eth"), "echo", args).unwrap();
+
+    assert_eq!(from_slice::<String>(&result).unwrap(), "hi");
+    assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn subinvocations_need_multi_threaded_runtime() {
+    let client = ClientBuilder::new()
+        .add_closure(uri!("ens/callee.eth"), echo())
+        .load()
+        .await
+        .unwrap();
+
+    let args = to_vec(&"hi".to_string()).unwrap();
+    let result = (client.subinvoker())(&uri!("ens/callee.eth"), "echo", args);
+
+    assert!(matches!(result, Err(InvokeError::WrapError { .. })));
+}
+
+/// Records its hooks into a shared log, optionally failing in `before`.
+struct Recorder {
+    name: &'static str,
+    fail: bool,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl InvokeMiddleware for Recorder {
+    async fn before(&self, request: &mut InvokeRequest) -> Result<(), InvokeError> {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("before {}", self.name));
+        if self.fail {
+            return Err(InvokeError::WrapError {
+                uri: request.uri.clone(),
+                method: request.method.clone(),
+                message: "rejected".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    async fn after(&self, _uri: &Uri, _method: &str, result: &mut Result<Vec<u8>, InvokeError>) {
+        let outcome = if result.is_ok() { "ok" } else { "err" };
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("after {} {outcome}", self.name));
+    }
+}
+
+#[tokio::test]
+async fn failing_before_only_unwinds_entered_middleware() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let recorder = |name, fail| Recorder {
+        name,
+        fail,
+        log: log.clone(),
+    };
+    let client = ClientBuilder::new()
+        .add_closure(uri!("ens/callee.eth"), echo())
+        .add_middleware(recorder("a", false))
+        .add_middleware(recorder("b", false))
+        .add_middleware(recorder("c", true))
+        .add_middleware(recorder("d", false))
+        .load()
+        .await
+        .unwrap();
+
+    let args = to_vec(&"hi".to_string()).unwrap();
+    let result = client
+        .invoke_raw(&uri!("ens/callee.eth"), "echo", args)
+        .await;
+
+    assert!(
+        matches!(result, Err(InvokeError::WrapError { ref message, .. }) if message == "rejected")
+    );
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "before a",
+            "before b",
+            "before c",
+            "after b err",
+            "after a err"
+        ]
+    );
+}
+
+/// Sends every invocation of `from` to `to`.
+struct Rewrite {
+    from: Uri,
+    to: Uri,
+}
+
+#[async_trait]
+impl InvokeMiddleware for Rewrite {
+    async fn before(&self, request: &mut InvokeRequest) -> Result<(), InvokeError> {
+        if request.uri == self.from {
+            request.uri = self.to.clone();
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn invoke_json_coerces_against_wrap_after_middleware() {
+    #[derive(serde::Deserialize)]
+    struct Args {
+        value: String,
+    }
+    let abi = Abi {
+        methods: vec![MethodDefinition {
+            name: "run".to_string(),
+            arguments: vec![PropertyDefinition {
+                name: "value".to_string(),
+                ty: TypeDefinition {
+                    kind: TypeKind::Scalar("BigInt".to_string()),
+                    required: true,
+                },
+            }],
+            return_type: None,
+        }],
+        ..Abi::default()
+    };
+    let typed = ClosureWrap::new()
+        .with_abi(abi)
+        .add_method("run", |args: &Args| Ok::<_, String>(args.value.clone()));
+    let client = ClientBuilder::new()
+        .add_closure(uri!("ens/typed.eth"), typed)
+        .add_closure(uri!("ens/untyped.eth"), ClosureWrap::new())
+        .add_middleware(Rewrite {
+            from: uri!("ens/untyped.eth"),
+            to: uri!("ens/typed.eth"),
+        })
+        .load()
+        .await
+        .unwrap();
+
+    // The number only decodes as a string if it was coerced with the BigInt from the typed wrap's ABI
+    let result = client
+        .invoke_json(
+            &uri!("ens/untyped.eth"),
+            "run",
+            serde_json::json!({ "value": 5 }),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(result, serde_json::json!("5"));
+}
+
+#[tokio::test]
+async fn invalid_args_report_nested_path() {
+    #[derive(serde::Serialize)]
+    struct Item {
+        count: u32,
+    }
+    #[derive(serde::Serialize)]
+    struct Args {
+        items: Vec<Item>,
+    }
+    let item = TypeDefinition {
+        kind: TypeKind::Object("Item".to_string()),
+        required: true,
+    };
+    let abi = Abi {
+        object_types: vec![ObjectDefinition {
+            name: "Item".to_string(),
+            properties: vec![PropertyDefinition {
+                name: "count".to_string(),
+                ty: TypeDefinition {
+                    kind: TypeKind::Scalar("UInt8".to_string()),
+                    required: true,
+                },
+            }],
+        }],
+        methods: vec![MethodDefinition {
+            name: "run".to_string(),
+            arguments: vec![PropertyDefinition {
+                name: "items".to_string(),
+                ty: TypeDefinition {
+                    kind: TypeKind::Array(Box::new(item)),
+                    required: true,
+                },
+            }],
+            return_type: None,
+        }],
+        ..Abi::default()
+    };
+    let client = ClientBuilder::new()
+        .add_closure(
+            uri!("ens/typed.eth"),
+            ClosureWrap::new()
+                .with_abi(abi)
+                .add_method("run", |_: &serde::de::IgnoredAny| Ok::<_, String>(true)),
+        )
+        .validate_against_abi()
+        .load()
+        .await
+        .unwrap();
+
+    let args = Args {
+        items: vec![Item { count: 1 }, Item { count: 256 }],
+    };
+    let result = client
+        .invoke_raw(&uri!("ens/typed.eth"), "run", to_vec(&args).unwrap())
+        .await;
+
+    match result {
+        Err(InvokeError::InvalidArgs {
+            path,
+            expected,
+            found,
+            ..
+        }) => {
+            assert_eq!(path, "items[1].count");
+            assert_eq!(expected, "UInt8!");
+            assert_eq!(found, "integer");
+        }
+        result => panic!("expected invalid args, got {:?}", result.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn batch_resolution_failure_is_batch_failed() {
+    let client = ClientBuilder::new().load().await.unwrap();
+
+    let results = client
+        .invoke_batch::<String, String>(
+            &uri!("ens/missing.eth"),
+            "echo",
+            vec!["a".into(), "b".into()],
+        )
+        .await;
+
+    assert_eq!(results.len(), 2);
+    for result in results {
+        match result {
+            Err(InvokeError::BatchFailed { source, .. }) => {
+                assert!(matches!(*source, InvokeError::WrapNotLoaded { .. }))
+            }
+            result => panic!("expected a failed batch, got {:?}", result),
+        }
+    }
+}
+
+/// Redirects `ens/alias.eth` to `ens/real.eth`, which resolves to the test wrap.
+struct AliasResolver;
+
+#[async_trait]
+impl UriResolver for AliasResolver {
+    async fn resolve(&self, uri: &Uri) -> Result<UriResolution, LoadError> {
+        if *uri == uri!("ens/alias.eth") {
+            Ok(UriResolution::Redirect(uri!("ens/real.eth")))
+        } else if *uri == uri!("ens/real.eth") {
+            let wrap = LoadedWrap::new_from_file("assets/test-wrap".into()).await?;
+            Ok(UriResolution::Wrap(Arc::new(Wrap::Loaded(wrap))))
+        } else {
+            Ok(UriResolution::NotFound)
+        }
+    }
+}
+
+#[tokio::test]
+async fn lockfile_pins_only_resolved_uri() {
+    let client = ClientBuilder::new()
+        .add_resolver(AliasResolver)
+        .load()
+        .await
+        .unwrap();
+
+    let (first, resolved_uri) = client
+        .resolve_wrap(&uri!("ens/alias.eth"), "m")
+        .await
+        .unwrap();
+    let (second, _) = client
+        .resolve_wrap(&uri!("ens/real.eth"), "m")
+        .await
+        .unwrap();
+    assert_eq!(resolved_uri, uri!("ens/real.eth"));
+    assert!(Arc::ptr_eq(&first, &second));
+
+    let mut lockfile = Lockfile::new();
+    client.update_lockfile(&mut lockfile).await;
+
+    let pinned: Vec<&String> = lockfile.wraps.keys().collect();
+    assert_eq!(pinned, [&uri!("ens/real.eth").to_string()]);
+}
+
+/// Logs "hello" and returns nil, whatever method is invoked.
+const LOGGING_WRAP: &str = r#"
+(module
+  (import "wrap" "__wrap_debug_log" (func $log (param i32 i32)))
+  (import "wrap" "__wrap_invoke_result" (func $result (param i32 i32)))
+  (import "env" "memory" (memory 1))
+  (data (i32.const 0) "hello")
+  (data (i32.const 16) "\c0")
+  (func (export "_wrap_invoke") (param i32 i32 i32) (result i32)
+    (call $log (i32.const 0) (i32.const 5))
+    (call $result (i32.const 16) (i32.const 1))
+    (i32.const 1)))
+"#;
+
+#[tokio::test]
+async fn debug_logs_go_to_the_handler_of_the_resolved_uri() {
+    let wrap_log = RingBufferDebugLog::new(2);
+    let default_log = RingBufferDebugLog::new(2);
+    let client = ClientBuilder::new()
+        .add_bytes(
+            uri!("ens/logger.eth"),
+            LOGGING_WRAP,
+            std::fs::read("assets/test-wrap/wrap.info").unwrap(),
+        )
+        .add_redirect(uri!("ens/alias.eth"), uri!("ens/logger.eth"))
+        .add_debug_log_handler(uri!("ens/logger.eth"), wrap_log.clone())
+        .with_debug_log_handler(default_log.clone())
+        .load()
+        .await
+        .unwrap();
+
+    for method in ["first", "second", "third"] {
+        client
+            .invoke::<(), ()>(&uri!("ens/alias.eth"), method, ())
+            .await
+            .unwrap();
+    }
+
+    // Only the last logs are kept, tagged with the uri that picked the handler
+    let entry = |method: &str| DebugLogEntry {
+        uri: uri!("ens/logger.eth"),
+        method: method.to_string(),
+        message: "hello".to_string(),
+    };
+    assert_eq!(wrap_log.entries(), [entry("second"), entry("third")]);
+    assert!(default_log.entries().is_empty());
+
+    wrap_log.clear();
+    assert!(wrap_log.entries().is_empty());
+}
+
+#[tokio::test]
+// Callbacks written against the old signature returned `InvokeError`s
+#[allow(clippy::result_large_err)]
+async fn closure_errors_are_reported_by_their_display() {
+    let client = ClientBuilder::new()
+        .add_closure(
+            uri!("ens/closure.eth"),
+            ClosureWrap::new()
+                .add_method("parse", |args: &String| args.parse::<u32>())
+                .add_method("fail", |_: &String| {
+                    Err::<(), _>(InvokeError::Cancelled {
+                        uri: uri!("ens/other.eth"),
+                        method: "other".to_string(),
+                    })
+                }),
+        )
+        .load()
+        .await
+        .unwrap();
+    let invoke = |method: &'static str, args: &'static str| {
+        let client = client.clone();
+        async move {
+            client
+                .invoke::<_, serde::de::IgnoredAny>(&uri!("ens/closure.eth"), method, args)
+                .await
+        }
+    };
+
+    assert!(invoke("parse", "12").await.is_ok());
+    match invoke("parse", "twelve").await {
+        Err(InvokeError::WrapError { message, .. }) => {
+            assert_eq!(message, "invalid digit found in string")
+        }
+        result => panic!("expected a wrap error, got {:?}", result.map(|_| ())),
+    }
+    assert!(matches!(
+        invoke("fail", "").await,
+        Err(InvokeError::WrapError { .. })
+    ));
+}
+
+#[tokio::test]
+async fn signer_of_resolved_wrap() {
+    let publisher = SigningKey::from_bytes(&[1; 32]);
+    let wasm = std::fs::read("assets/test-wrap/wrap.wasm").unwrap();
+    let manifest = std::fs::read("assets/test-wrap/wrap.info").unwrap();
+    let signature = sign_wrap(&publisher, &WrapHashes::compute(&wasm, &manifest));
+    let client = ClientBuilder::new()
+        .add_signed_bytes(
+            uri!("ens/signed.eth"),
+            wasm.clone(),
+            manifest.clone(),
+            signature,
+        )
+        .add_bytes(uri!("ens/unsigned.eth"), wasm, manifest)
+        .add_redirect(uri!("ens/alias.eth"), uri!("ens/signed.eth"))
+        .add_trusted_key(publisher.verifying_key())
+        .load()
+        .await
+        .unwrap();
+
+    let signer = Some(publisher.verifying_key());
+    assert_eq!(client.signer(&uri!("ens/signed.eth")).await, signer);
+    assert_eq!(client.signer(&uri!("ens/alias.eth")).await, signer);
+    assert_eq!(client.signer(&uri!("ens/unsigned.eth")).await, None);
+    assert_eq!(client.signer(&uri!("ens/missing.eth")).await, None);
+}
+
+#[tokio::test]
+async fn invocations_follow_a_redirect() {
+    let client = ClientBuilder::new()
+        .add_closure(uri!("ens/callee.eth"), echo())
+        .add_redirect(uri!("ens/alias.eth"), uri!("ens/callee.eth"))
+        .load()
+        .await
+        .unwrap();
+
+    let result: String = client
+        .invoke(&uri!("ens/alias.eth"), "echo", "hi".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(result, "hi");
+}
+
+#[tokio::test]
+async fn invocations_follow_chained_redirects() {
+    let client = ClientBuilder::new()
+        .add_closure(uri!("ens/callee.eth"), echo())
+        .add_redirect(uri!("ens/a.eth"), uri!("ens/b.eth"))
+        .add_redirect(uri!("ens/b.eth"), uri!("ens/callee.eth"))
+        .load()
+        .await
+        .unwrap();
+
+    let result: String = client
+        .invoke(&uri!("ens/a.eth"), "echo", "hi".to_string())
+        .await
+        .unwrap();
+
+    assert_eq!(result, "hi");
+}
+
+#[tokio::test]
+async fn redirect_cycles_fail_to_load() {
+    let result = ClientBuilder::new()
+        .add_redirect(uri!("ens/a.eth"), uri!("ens/b.eth"))
+        .add_redirect(uri!("ens/b.eth"), uri!("ens/a.eth"))
+        .load()
+        .await;
+
+    // The cycle is reported from whichever uri is checked first
+    match result {
+        Err(LoadError::RedirectCycle(chain)) => {
+            assert_eq!(chain.len(), 3);
+            assert_eq!(chain[0], chain[2]);
+            assert_ne!(chain[0], chain[1]);
+        }
+        _ => panic!("expected a redirect cycle"),
+    }
+}
@@ -0,0 +1,349 @@
+use super::json::join;
+use crate::{Abi, PropertyDefinition, TypeDefinition, TypeKind, Value, GENERIC_MAP_EXT};
+
+/// Where a value stops matching its declared type.
+pub(crate) struct Mismatch {
+    pub path: String,
+    pub expected: String,
+    pub found: String,
+}
+
+/// Checks msgpack encoded args against the arguments `method` declares.
+pub(crate) fn validate_args(
+    abi: &Abi,
+    arguments: &[PropertyDefinition],
+    args: &[u8],
+) -> Result<(), Mismatch> {
+    let args = decode(args, "args")?;
+    match &args {
+        Value::Map(_) => validate_properties(abi, arguments, &args, ""),
+        _ => Err(mismatch("args", "map", &args)),
+    }
+}
+
+/// Checks a msgpack encoded result against the return type of a method.
+pub(crate) fn validate_result(
+    abi: &Abi,
+    return_type: &TypeDefinition,
+    result: &[u8],
+) -> Result<(), Mismatch> {
+    let result = decode(result, "return")?;
+    validate(abi, return_type, &result, "return")
+}
+
+fn decode(bytes: &[u8], path: &str) -> Result<Value, Mismatch> {
+    Value::decode(bytes).map_err(|error| Mismatch {
+        path: path.to_string(),
+        expected: "msgpack".to_string(),
+        found: error.to_string(),
+    })
+}
+
+fn validate(abi: &Abi, ty: &TypeDefinition, value: &Value, path: &str) -> Result<(), Mismatch> {
+    let valid = match (&ty.kind, value) {
+        // Wraps encode empty bytes as nil
+        (TypeKind::Scalar(name), Value::Nil) if name == "Bytes" => true,
+        (_, Value::Nil) => !ty.required,
+        (TypeKind::Scalar(name), value) => is_scalar(name, value),
+        // Enums are encoded as the index of their constant
+        (TypeKind::Enum(name), value) => match abi.enum_type(name) {
+            Some(enum_type) => match value {
+                Value::UInt(index) => {
+                    usize::try_from(*index).is_ok_and(|index| index < enum_type.constants.len())
+                }
+                Value::Int(index) => {
+                    usize::try_from(*index).is_ok_and(|index| index < enum_type.constants.len())
+                }
+                _ => false,
+            },
+            None => true,
+        },
+        (TypeKind::Object(name), value) => match abi.object_type(name) {
+            Some(object) => match value {
+                Value::Map(_) => return validate_properties(abi, &object.properties, value, path),
+                _ => false,
+            },
+            None => true,
+        },
+        (TypeKind::Array(item), Value::Array(items)) => {
+            for (i, value) in items.iter().enumerate() {
+                validate(abi, item, value, &format!("{}[{}]", path, i))?;
+            }
+            true
+        }
+        (TypeKind::Map(key_type, value_type), value) => {
+            let decoded;
+            let entries = match value {
+                Value::Ext(GENERIC_MAP_EXT, data) => match Value::decode(data) {
+                    Ok(Value::Map(entries)) => {
+                        decoded = entries;
+                        &decoded
+                    }
+                    _ => return Err(mismatch(path, &ty.name(), value)),
+                },
+                Value::Map(entries) => entries,
+                _ => return Err(mismatch(path, &ty.name(), value)),
+            };
+            for (key, value) in entries {
+                let key_path = match key {
+                    Value::String(key) => join(path, key),
+                    key => join(path, &key.to_json().to_string()),
+                };
+                validate(abi, key_type, key, &key_path)?;
+                validate(abi, value_type, value, &key_path)?;
+            }
+            true
+        }
+        _ => false,
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(mismatch(path, &ty.name(), value)),
+    }
+}
+
+/// Properties the ABI doesn't know are ignored.
+fn validate_properties(
+    abi: &Abi,
+    properties: &[PropertyDefinition],
+    object: &Value,
+    path: &str,
+) -> Result<(), Mismatch> {
+    for property in properties {
+        let path = join(path, &property.name);
+        match object.get(&property.name) {
+            Some(value) => validate(abi, &property.ty, value, &path)?,
+            None if property.ty.required => {
+                return Err(Mismatch {
+                    path,
+                    expected: property.ty.name(),
+                    found: "nothing".to_string(),
+                })
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+fn is_scalar(name: &str, value: &Value) -> bool {
+    let integer = match value {
+        Value::UInt(value) => Some(*value as i128),
+        Value::Int(value) => Some(*value as i128),
+        _ => None,
+    };
+    let in_range = |min: i128, max: i128| integer.is_some_and(|value| (min..=max).contains(&value));
+
+    match name {
+        "UInt8" => in_range(0, u8::MAX as i128),
+        "UInt16" => in_range(0, u16::MAX as i128),
+        "UInt" | "UInt32" => in_range(0, u32::MAX as i128),
+        "Int8" => in_range(i8::MIN as i128, i8::MAX as i128),
+        "Int16" => in_range(i16::MIN as i128, i16::MAX as i128),
+        "Int" | "Int32" => in_range(i32::MIN as i128, i32::MAX as i128),
+        "UInt64" => in_range(0, u64::MAX as i128),
+        "Int64" => in_range(i64::MIN as i128, i64::MAX as i128),
+        "Float32" | "Float64" => matches!(value, Value::F32(_) | Value::F64(_)),
+        "Boolean" => matches!(value, Value::Bool(_)),
+        "String" | "BigInt" | "BigNumber" | "JSON" => matches!(value, Value::String(_)),
+        "Bytes" => matches!(value, Value::Binary(_)),
+        _ => true,
+    }
+}
+
+fn mismatch(path: &str, expected: &str, found: &Value) -> Mismatch {
+    let found = match found {
+        Value::Nil => "nil",
+        Value::Bool(_) => "boolean",
+        Value::UInt(_) | Value::Int(_) => "integer",
+        Value::F32(_) | Value::F64(_) => "float",
+        Value::String(_) => "string",
+        Value::Binary(_) => "bytes",
+        Value::Array(_) => "array",
+        Value::Map(_) => "map",
+        Value::Ext(..) => "extension",
+    };
+    Mismatch {
+        path: path.to_string(),
+        expected: expected.to_string(),
+        found: found.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EnumDefinition, MethodDefinition, ObjectDefinition};
+
+    fn ty(kind: TypeKind, required: bool) -> TypeDefinition {
+        TypeDefinition { kind, required }
+    }
+
+    fn property(name: &str, ty: TypeDefinition) -> PropertyDefinition {
+        PropertyDefinition {
+            name: name.to_string(),
+            ty,
+        }
+    }
+
+    fn scalar(name: &str) -> TypeKind {
+        TypeKind::Scalar(name.to_string())
+    }
+
+    /// `run(outer: Outer!, flag: Boolean)` where `Outer { items: [Item!]!, label: String! }` and
+    /// `Item { count: UInt8!, color: Color }`.
+    fn abi() -> Abi {
+        let item = TypeKind::Object("Item".to_string());
+        Abi {
+            object_types: vec![
+                ObjectDefinition {
+                    name: "Outer".to_string(),
+                    properties: vec![
+                        property("items", ty(TypeKind::Array(Box::new(ty(item, true))), true)),
+                        property("label", ty(scalar("String"), true)),
+                    ],
+                },
+                ObjectDefinition {
+                    name: "Item".to_string(),
+                    properties: vec![
+                        property("count", ty(scalar("UInt8"), true)),
+                        property("color", ty(TypeKind::Enum("Color".to_string()), false)),
+                    ],
+                },
+            ],
+            enum_types: vec![EnumDefinition {
+                name: "Color".to_string(),
+                constants: vec!["RED".to_string(), "GREEN".to_string()],
+            }],
+            methods: vec![MethodDefinition {
+                name: "run".to_string(),
+                arguments: vec![
+                    property("outer", ty(TypeKind::Object("Outer".to_string()), true)),
+                    property("flag", ty(scalar("Boolean"), false)),
+                ],
+                return_type: None,
+            }],
+        }
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Value::String(key.to_string()), value))
+                .collect(),
+        )
+    }
+
+    fn item(count: Value, color: Value) -> Value {
+        map(vec![("count", count), ("color", color)])
+    }
+
+    fn args(items: Vec<Value>, label: Value) -> Value {
+        map(vec![
+            (
+                "outer",
+                map(vec![("items", Value::Array(items)), ("label", label)]),
+            ),
+            ("flag", Value::Nil),
+        ])
+    }
+
+    fn check(args: Value) -> Result<(), (String, String, String)> {
+        let abi = abi();
+        validate_args(&abi, &abi.methods[0].arguments, &args.encode())
+            .map_err(|mismatch| (mismatch.path, mismatch.expected, mismatch.found))
+    }
+
+    fn mismatch(path: &str, expected: &str, found: &str) -> Result<(), (String, String, String)> {
+        Err((path.to_string(), expected.to_string(), found.to_string()))
+    }
+
+    #[test]
+    fn valid_args() {
+        let items = vec![
+            item(Value::UInt(1), Value::UInt(0)),
+            item(Value::UInt(255), Value::Nil),
+        ];
+
+        assert!(check(args(items, Value::String("a".into()))).is_ok());
+    }
+
+    #[test]
+    fn missing_required_field() {
+        assert_eq!(
+            check(map(vec![("flag", Value::Bool(true))])),
+            mismatch("outer", "Outer!", "nothing")
+        );
+        assert_eq!(
+            check(args(vec![map(vec![])], Value::String("a".into()))),
+            mismatch("outer.items[0].count", "UInt8!", "nothing")
+        );
+    }
+
+    #[test]
+    fn wrong_scalar_type() {
+        assert_eq!(
+            check(args(vec![], Value::UInt(1))),
+            mismatch("outer.label", "String!", "integer")
+        );
+    }
+
+    #[test]
+    fn wide_and_float_scalars() {
+        assert!(is_scalar("UInt64", &Value::UInt(u64::MAX)));
+        assert!(!is_scalar("UInt64", &Value::Int(-1)));
+        assert!(is_scalar("Int64", &Value::Int(i64::MIN)));
+        assert!(!is_scalar("Int64", &Value::UInt(u64::MAX)));
+        assert!(is_scalar("Float32", &Value::F32(1.5)));
+        assert!(is_scalar("Float64", &Value::F64(1.5)));
+        assert!(!is_scalar("Float64", &Value::String("1.5".into())));
+    }
+
+    #[test]
+    fn null_in_non_nullable_field() {
+        assert_eq!(
+            check(args(vec![], Value::Nil)),
+            mismatch("outer.label", "String!", "nil")
+        );
+    }
+
+    #[test]
+    fn out_of_range_enum() {
+        let color = |color| {
+            check(args(
+                vec![item(Value::UInt(0), color)],
+                Value::String("a".into()),
+            ))
+        };
+
+        assert!(color(Value::UInt(1)).is_ok());
+        assert_eq!(
+            color(Value::UInt(2)),
+            mismatch("outer.items[0].color", "Color", "integer")
+        );
+        assert_eq!(
+            color(Value::Int(-1)),
+            mismatch("outer.items[0].color", "Color", "integer")
+        );
+        // Constants are never encoded by name
+        assert_eq!(
+            color(Value::String("RED".into())),
+            mismatch("outer.items[0].color", "Color", "string")
+        );
+    }
+
+    #[test]
+    fn nested_path() {
+        let items = vec![
+            item(Value::UInt(1), Value::Nil),
+            item(Value::UInt(256), Value::Nil),
+        ];
+
+        assert_eq!(
+            check(args(items, Value::String("a".into()))),
+            mismatch("outer.items[1].count", "UInt8!", "integer")
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::LoadError;
+use flate2::read::GzDecoder;
+use std::{
+    io::{Cursor, Read},
+    path::{Component, Path},
+};
+
+/// Largest archive file we are willing to read.
+pub const MAX_ARCHIVE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// What we are willing to extract from an archive, to guard against decompression bombs.
+struct Limits {
+    /// Largest decompressed file
+    entry_size: u64,
+    /// Most bytes decompressed in total, including the entries that are skipped
+    total_size: u64,
+    entries: usize,
+}
+
+const LIMITS: Limits = Limits {
+    entry_size: 64 * 1024 * 1024,
+    total_size: 128 * 1024 * 1024,
+    entries: 1024,
+};
+
+/// The files of a wrap package, extracted from an archive.
+pub struct ArchiveContents {
+    pub wasm: Vec<u8>,
+    pub manifest: Vec<u8>,
+    pub signature: Option<Vec<u8>>,
+}
+
+/// Extracts `wrap.wasm`, `wrap.info` and optionally `wrap.sig` from a tar, gzipped tar or zip archive.
+/// The files may sit at the root of the archive or inside a single directory.
+pub fn read_archive(bytes: &[u8]) -> Result<ArchiveContents, LoadError> {
+    read_archive_within(bytes, &LIMITS)
+}
+
+fn read_archive_within(bytes: &[u8], limits: &Limits) -> Result<ArchiveContents, LoadError> {
+    if bytes.starts_with(b"PK\x03\x04") {
+        read_zip(bytes, limits)
+    } else if bytes.starts_with(&[0x1f, 0x8b]) {
+        read_tar(GzDecoder::new(bytes), limits)
+    } else {
+        read_tar(bytes, limits)
+    }
+}
+
+fn read_tar<R: Read>(reader: R, limits: &Limits) -> Result<ArchiveContents, LoadError> {
+    // Tar entries are read one after the other, so skipping an entry still decompresses it
+    let mut archive = tar::Archive::new(Budget {
+        reader,
+        remaining: limits.total_size,
+        exceeded: false,
+    });
+    let mut contents = PartialContents::new(limits);
+
+    let result = read_tar_entries(&mut archive, &mut contents);
+    if archive.into_inner().exceeded {
+        return Err(too_large());
+    }
+    result?;
+
+    contents.finish()
+}
+
+fn read_tar_entries<R: Read>(
+    archive: &mut tar::Archive<R>,
+    contents: &mut PartialContents,
+) -> Result<(), LoadError> {
+    for entry in archive.entries().map_err(invalid_archive)? {
+        let entry = entry.map_err(invalid_archive)?;
+        let path = entry.path().map_err(invalid_archive)?.into_owned();
+        let is_file = entry.header().entry_type().is_file();
+        contents.add(&path, is_file, entry)?;
+    }
+    Ok(())
+}
+
+/// Fails reads once more than `remaining` bytes have been read.
+struct Budget<R> {
+    reader: R,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl<R: Read> Read for Budget<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Reading one byte past the budget tells an archive that fits exactly from one that doesn't
+        let len = buf.len().min(self.remaining.saturating_add(1) as usize);
+        let read = self.reader.read(&mut buf[..len])?;
+        if read as u64 > self.remaining {
+            self.exceeded = true;
+            return Err(std::io::Error::other(
+                "archive is too large once decompressed",
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+fn read_zip(bytes: &[u8], limits: &Limits) -> Result<ArchiveContents, LoadError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(invalid_archive)?;
+    let mut contents = PartialContents::new(limits);
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i).map_err(invalid_archive)?;
+        let path = Path::new(entry.name()).to_path_buf();
+        let is_file = entry.is_file();
+        contents.add(&path, is_file, entry)?;
+    }
+
+    contents.finish()
+}
+
+struct PartialContents<'a> {
+    limits: &'a Limits,
+    wasm: Option<Vec<u8>>,
+    manifest: Option<Vec<u8>>,
+    signature: Option<Vec<u8>>,
+    entries: usize,
+    /// Bytes of the files extracted so far
+    extracted: u64,
+}
+
+impl<'a> PartialContents<'a> {
+    fn new(limits: &'a Limits) -> Self {
+        Self {
+            limits,
+            wasm: None,
+            manifest: None,
+            signature: None,
+            entries: 0,
+            extracted: 0,
+        }
+    }
+
+    fn add<R: Read>(&mut self, path: &Path, is_file: bool, reader: R) -> Result<(), LoadError> {
+        self.entries += 1;
+        if self.entries > self.limits.entries {
+            return Err(LoadError::InvalidArchive("too many entries".to_string()));
+        }
+
+        // Even though nothing is written to disk, refuse archives that try to escape their root
+        if path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(LoadError::InvalidArchive(format!(
+                "unsafe entry path {}",
+                path.display()
+            )));
+        }
+
+        if !is_file {
+            return Ok(());
+        }
+
+        let depth = path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .count();
+        let slot = match path.file_name().and_then(|name| name.to_str()) {
+            Some("wrap.wasm") if depth <= 2 => &mut self.wasm,
+            Some("wrap.info") if depth <= 2 => &mut self.manifest,
+            Some("wrap.sig") if depth <= 2 => &mut self.signature,
+            _ => return Ok(()),
+        };
+        if slot.is_some() {
+            return Err(LoadError::InvalidArchive(format!(
+                "duplicate entry {}",
+                path.display()
+            )));
+        }
+
+        let mut buffer = Vec::new();
+        reader
+            .take(self.limits.entry_size + 1)
+            .read_to_end(&mut buffer)
+            .map_err(invalid_archive)?;
+        if buffer.len() as u64 > self.limits.entry_size {
+            return Err(LoadError::InvalidArchive(format!(
+                "entry {} is too large",
+                path.display()
+            )));
+        }
+
+        // Zip entries that are skipped aren't decompressed, only the extracted ones count
+        self.extracted += buffer.len() as u64;
+        if self.extracted > self.limits.total_size {
+            return Err(too_large());
+        }
+
+        *slot = Some(buffer);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<ArchiveContents, LoadError> {
+        match (self.wasm, self.manifest) {
+            (Some(wasm), Some(manifest)) => Ok(ArchiveContents {
+                wasm,
+                manifest,
+                signature: self.signature,
+            }),
+            (None, _) => Err(LoadError::InvalidArchive("missing wrap.wasm".to_string())),
+            (_, None) => Err(LoadError::InvalidArchive("missing wrap.info".to_string())),
+        }
+    }
+}
+
+fn too_large() -> LoadError {
+    LoadError::InvalidArchive("archive is too large once decompressed".to_string())
+}
+
+fn invalid_archive<E: ToString>(error: E) -> LoadError {
+    LoadError::InvalidArchive(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    const TEST_LIMITS: Limits = Limits {
+        entry_size: 16,
+        total_size: 8 * 1024,
+        entries: 4,
+    };
+
+    const WRAP: [(&str, &[u8]); 2] = [("wrap.wasm", b"wasm"), ("wrap.info", b"info")];
+
+    /// Writes the names as given, unlike `tar::Header::set_path`, which refuses unsafe paths.
+    fn tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_mut_bytes()[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// The archive in every format.
+    fn archives(entries: &[(&str, &[u8])]) -> [(&'static str, Vec<u8>); 3] {
+        [
+            ("tar", tar(entries)),
+            ("tar.gz", gzip(&tar(entries))),
+            ("zip", zip(entries)),
+        ]
+    }
+
+    fn assert_invalid(entries: &[(&str, &[u8])], reason: &str) {
+        for (format, archive) in archives(entries) {
+            match read_archive_within(&archive, &TEST_LIMITS) {
+                Err(LoadError::InvalidArchive(message)) => {
+                    assert!(message.contains(reason), "{}: {}", format, message)
+                }
+                Err(error) => panic!("{}: unexpected error {}", format, error),
+                Ok(_) => panic!("{}: archive was accepted", format),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_wrap_files() {
+        let nested = [
+            ("pkg/wrap.wasm", b"wasm" as &[u8]),
+            ("pkg/wrap.info", b"info"),
+        ];
+        for entries in [&WRAP[..], &nested[..]] {
+            for (format, archive) in archives(entries) {
+                let contents = read_archive_within(&archive, &TEST_LIMITS)
+                    .unwrap_or_else(|error| panic!("{}: {}", format, error));
+                assert_eq!(contents.wasm, b"wasm");
+                assert_eq!(contents.manifest, b"info");
+                assert!(contents.signature.is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        assert_invalid(&[("../wrap.wasm", b"wasm"), WRAP[1]], "unsafe entry path");
+        assert_invalid(
+            &[WRAP[0], ("pkg/../../wrap.info", b"info")],
+            "unsafe entry path",
+        );
+        assert_invalid(&[("/wrap.wasm", b"wasm"), WRAP[1]], "unsafe entry path");
+    }
+
+    #[test]
+    fn rejects_oversized_entries() {
+        let large = [0; 17];
+        assert_invalid(&[("wrap.wasm", &large), WRAP[1]], "too large");
+
+        // Files we don't extract may be larger
+        let entries = [WRAP[0], WRAP[1], ("README", &large)];
+        for (format, archive) in archives(&entries) {
+            read_archive_within(&archive, &TEST_LIMITS)
+                .unwrap_or_else(|error| panic!("{}: {}", format, error));
+        }
+    }
+
+    #[test]
+    fn bounds_total_decompressed_size() {
+        // Skipped tar entries are decompressed too
+        let skipped = [0; 8 * 1024];
+        let entries = [WRAP[0], WRAP[1], ("README", &skipped)];
+        for archive in [tar(&entries), gzip(&tar(&entries))] {
+            match read_archive_within(&archive, &TEST_LIMITS) {
+                Err(LoadError::InvalidArchive(message)) => {
+                    assert_eq!(message, "archive is too large once decompressed")
+                }
+                _ => panic!("archive was accepted"),
+            }
+        }
+
+        let limits = Limits {
+            total_size: 40,
+            ..TEST_LIMITS
+        };
+        let full: &[u8] = &[1; 16];
+        let entries = [("wrap.wasm", full), ("wrap.info", full), ("wrap.sig", full)];
+        match read_archive_within(&zip(&entries), &limits) {
+            Err(LoadError::InvalidArchive(message)) => {
+                assert_eq!(message, "archive is too large once decompressed")
+            }
+            _ => panic!("archive was accepted"),
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_entries() {
+        assert_invalid(
+            &[WRAP[0], WRAP[1], ("pkg/wrap.wasm", b"other")],
+            "duplicate entry",
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_entries() {
+        let entries = [WRAP[0], WRAP[1], ("a", b""), ("b", b""), ("c", b"")];
+        assert_invalid(&entries, "too many entries");
+    }
+
+    #[test]
+    fn rejects_missing_files() {
+        assert_invalid(&[WRAP[1]], "missing wrap.wasm");
+        assert_invalid(&[WRAP[0]], "missing wrap.info");
+    }
+}
@@ -0,0 +1,136 @@
+use addr2line::gimli::{Dwarf, EndianSlice, LittleEndian, SectionId};
+use std::{collections::HashMap, fmt};
+
+/// A frame of a wasm trap, resolved to a function name and, if the module has DWARF info, a source location.
+#[derive(Clone, Debug)]
+pub struct BacktraceFrame {
+    pub func_index: u32,
+    /// Demangled name from the module's name section
+    pub function_name: Option<String>,
+    pub module_offset: usize,
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceLocation {
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+impl fmt::Display for BacktraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function_name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "<wasm function {}>", self.func_index)?,
+        }
+
+        match &self.location {
+            Some(location) => {
+                write!(f, " at {}", location.file.as_deref().unwrap_or("<unknown>"))?;
+                if let Some(line) = location.line {
+                    write!(f, ":{}", line)?;
+                }
+                if let Some(column) = location.column {
+                    write!(f, ":{}", column)?;
+                }
+                Ok(())
+            }
+            None => write!(f, " at offset {:#x}", self.module_offset),
+        }
+    }
+}
+
+/// The DWARF sections of a wasm module, kept around to symbolicate traps.
+///
+/// Parsing DWARF is only done when a trap actually happens, so loading wraps stays cheap.
+#[derive(Default)]
+pub struct DebugInfo {
+    /// DWARF addresses are relative to the start of the code section
+    code_offset: usize,
+    sections: HashMap<&'static str, Vec<u8>>,
+}
+
+impl DebugInfo {
+    pub fn new(wasm: &[u8]) -> Self {
+        let mut debug_info = Self::default();
+
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            match payload {
+                Ok(wasmparser::Payload::CodeSectionStart { range, .. }) => {
+                    debug_info.code_offset = range.start;
+                }
+                Ok(wasmparser::Payload::CustomSection(section)) => {
+                    if let Some(id) = dwarf_section_id(section.name()) {
+                        debug_info
+                            .sections
+                            .insert(id.name(), section.data().to_vec());
+                    }
+                }
+                Ok(_) => {}
+                // The module has already been validated by wasmer, a parse error here only means no debug info
+                Err(_) => return Self::default(),
+            }
+        }
+
+        debug_info
+    }
+
+    /// Resolves the frames of a trap, innermost first.
+    pub fn symbolicate(&self, trace: &[wasmer::FrameInfo]) -> Vec<BacktraceFrame> {
+        let context = self.context();
+
+        trace
+            .iter()
+            .map(|frame| {
+                let location = context.as_ref().and_then(|context| {
+                    let address = frame.module_offset().checked_sub(self.code_offset)?;
+                    let location = context.find_location(address as u64).ok()??;
+                    Some(SourceLocation {
+                        file: location.file.map(str::to_string),
+                        line: location.line,
+                        column: location.column,
+                    })
+                });
+
+                BacktraceFrame {
+                    func_index: frame.func_index(),
+                    function_name: frame
+                        .function_name()
+                        .map(|name| rustc_demangle::demangle(name).to_string()),
+                    module_offset: frame.module_offset(),
+                    location,
+                }
+            })
+            .collect()
+    }
+
+    fn context(&self) -> Option<addr2line::Context<EndianSlice<'_, LittleEndian>>> {
+        if self.sections.is_empty() {
+            return None;
+        }
+
+        let dwarf = Dwarf::load(|id| -> Result<_, addr2line::gimli::Error> {
+            let data = self.sections.get(id.name()).map(Vec::as_slice);
+            Ok(EndianSlice::new(data.unwrap_or(&[]), LittleEndian))
+        })
+        .ok()?;
+        addr2line::Context::from_dwarf(dwarf).ok()
+    }
+}
+
+fn dwarf_section_id(name: &str) -> Option<SectionId> {
+    [
+        SectionId::DebugAbbrev,
+        SectionId::DebugAddr,
+        SectionId::DebugInfo,
+        SectionId::DebugLine,
+        SectionId::DebugLineStr,
+        SectionId::DebugRanges,
+        SectionId::DebugRngLists,
+        SectionId::DebugStr,
+        SectionId::DebugStrOffsets,
+    ]
+    .into_iter()
+    .find(|id| id.name() == name)
+}
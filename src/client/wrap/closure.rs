@@ -1,44 +1,96 @@
-use crate::InvokeError;
+use crate::{Abi, InvokeError};
 use polywrap_msgpack_serde::{from_slice, to_vec};
+use polywrap_uri::Uri;
 use serde::{de::DeserializeOwned, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display};
 
+type ClosureMethod = Box<dyn Fn(&[u8]) -> Result<Vec<u8>, ClosureError> + Send + Sync>;
+
+/// Errors from a closure method, before the uri and method are attached.
+enum ClosureError {
+    Decode(polywrap_msgpack_serde::Error),
+    Encode(polywrap_msgpack_serde::Error),
+    Callback(String),
+}
+
+#[derive(Default)]
 pub struct ClosureWrap {
-    closure: HashMap<String, Box<dyn Fn(&[u8]) -> Result<Vec<u8>, InvokeError> + Send + Sync>>,
+    closure: HashMap<String, ClosureMethod>,
+    pub abi: Option<Abi>,
 }
 
 impl ClosureWrap {
     pub fn new() -> Self {
         Self {
             closure: HashMap::new(),
+            abi: None,
         }
     }
 
-    pub fn add_method<Input: DeserializeOwned, Output: Serialize>(
+    /// Lets invocations be checked and converted against `abi`, like the manifest of a loaded wrap.
+    pub fn with_abi(mut self, abi: Abi) -> Self {
+        self.abi = Some(abi);
+        self
+    }
+
+    /// Errors returned by `callback` are reported to the caller as [`InvokeError::WrapError`], with their
+    /// `Display` output as the message.
+    pub fn add_method<Input: DeserializeOwned, Output: Serialize, E: Display>(
+        self,
+        method: &str,
+        callback: impl Fn(&Input) -> Result<Output, E> + Send + Sync + 'static,
+    ) -> Self {
+        self.add_owned_method(method, move |args: Input| callback(&args))
+    }
+
+    /// Like [`ClosureWrap::add_method`], for callbacks that take ownership of their args.
+    pub fn add_owned_method<Input: DeserializeOwned, Output: Serialize, E: Display>(
         mut self,
         method: &str,
-        callback: impl Fn(&Input) -> Result<Output, InvokeError> + Send + Sync + 'static,
+        callback: impl Fn(Input) -> Result<Output, E> + Send + Sync + 'static,
     ) -> Self {
         self.closure.insert(
             method.to_string(),
             Box::new(move |args| {
-                let args = from_slice(args).map_err(InvokeError::MsgpackDeserialize)?;
-                let result = callback(&args)?;
-                let result = to_vec(&result).map_err(InvokeError::MsgpackSerialize)?;
+                let args = from_slice(args).map_err(ClosureError::Decode)?;
+                let result =
+                    callback(args).map_err(|error| ClosureError::Callback(error.to_string()))?;
+                let result = to_vec(&result).map_err(ClosureError::Encode)?;
                 Ok(result)
             }),
         );
         self
     }
 
-    pub async fn invoke(&self, method: &str, args: &[u8]) -> Result<Vec<u8>, InvokeError> {
-        let closure = self
-            .closure
-            .get(method)
-            .ok_or(InvokeError::MethodNotFound)?;
+    pub async fn invoke(
+        &self,
+        uri: &Uri,
+        method: &str,
+        args: &[u8],
+    ) -> Result<Vec<u8>, InvokeError> {
+        let uri = uri.clone();
+        let method = method.to_string();
 
-        let result = closure(&args)?;
+        let Some(closure) = self.closure.get(&method) else {
+            return Err(InvokeError::MethodNotFound { uri, method });
+        };
 
-        Ok(result)
+        closure(args).map_err(|error| match error {
+            ClosureError::Decode(source) => InvokeError::DecodeFailed {
+                uri,
+                method,
+                source,
+            },
+            ClosureError::Encode(source) => InvokeError::EncodeFailed {
+                uri,
+                method,
+                source,
+            },
+            ClosureError::Callback(message) => InvokeError::WrapError {
+                uri,
+                method,
+                message,
+            },
+        })
     }
 }
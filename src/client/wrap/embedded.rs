@@ -0,0 +1,7 @@
+/// A wrap compiled into the binary with [`embed_wrap!`](crate::embed_wrap).
+pub struct EmbeddedWrap {
+    pub wasm: &'static [u8],
+    pub manifest: &'static [u8],
+    /// The wrap's `wrap.sig`, if it was signed
+    pub signature: Option<&'static [u8]>,
+}
@@ -1,5 +1,6 @@
 // This file is heavily modified from: rust-client\packages\wasm\src\runtime\imports.rs
 use super::State;
+use polywrap_uri::Uri;
 
 pub fn create(
     memory: wasmer::Memory,
@@ -45,8 +46,12 @@ fn wrap_invoke_args(context: Context, method_ptr: i32, args_ptr: i32) -> Result<
     }
 
     let memory_view = data.memory.view(&context);
-    memory_view.write(method_ptr as u64, &data.method)?;
-    memory_view.write(args_ptr as u64, &data.args)?;
+    memory_view
+        .write(method_ptr as u64, &data.method)
+        .map_err(memory_error)?;
+    memory_view
+        .write(args_ptr as u64, &data.args)
+        .map_err(memory_error)?;
 
     Ok(())
 }
@@ -56,7 +61,9 @@ fn wrap_invoke_result(mut context: Context, offset: i32, length: i32) -> Result<
 
     let memory_view = data.memory.view(&store);
     let mut buffer: Vec<u8> = empty_buffer(length);
-    memory_view.read(offset as u64, &mut buffer)?;
+    memory_view
+        .read(offset as u64, &mut buffer)
+        .map_err(memory_error)?;
     data.invoke = Some(Ok(buffer));
     Ok(())
 }
@@ -86,12 +93,35 @@ fn wrap_abort(
     let msg = string_from_memory(&memory_view, msg_length, msg_offset, "__wrap_abort")?;
     let file = string_from_memory(&memory_view, file_length, file_offset, "__wrap_abort")?;
 
-    Err(error(&format!(
-        "Abort: {}\nFile: {}\nLocation: [{}, {}]",
-        msg, file, line, column
-    )))
+    Err(wasmer::RuntimeError::user(Box::new(Abort {
+        message: msg,
+        file,
+        line: line as u32,
+        column: column as u32,
+    })))
 }
 
+/// Raised through the wasm call stack by `__wrap_abort`, so the instance can tell it apart from host traps.
+#[derive(Debug)]
+pub struct Abort {
+    pub message: String,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl std::fmt::Display for Abort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}:{}:{})",
+            self.message, self.file, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for Abort {}
+
 fn wrap_subinvoke(
     mut context: Context,
     uri_ptr: i32,
@@ -108,12 +138,30 @@ fn wrap_subinvoke(
     let mut method_buffer: Vec<u8> = empty_buffer(method_len);
     let mut args_buffer: Vec<u8> = empty_buffer(args_len);
 
-    memory_view.read(uri_ptr as u64, &mut uri_buffer)?;
-    memory_view.read(method_ptr as u64, &mut method_buffer)?;
-    memory_view.read(args_ptr as u64, &mut args_buffer)?;
-
-    // TODO: Handle the subinvoke logic
-    unimplemented!("wrap_subinvoke")
+    memory_view
+        .read(uri_ptr as u64, &mut uri_buffer)
+        .map_err(memory_error)?;
+    memory_view
+        .read(method_ptr as u64, &mut method_buffer)
+        .map_err(memory_error)?;
+    memory_view
+        .read(args_ptr as u64, &mut args_buffer)
+        .map_err(memory_error)?;
+
+    let uri = String::from_utf8(uri_buffer)
+        .ok()
+        .and_then(|uri| Uri::try_from(uri).ok())
+        .ok_or_else(|| error("__wrap_subinvoke: invalid uri"))?;
+    let method =
+        String::from_utf8(method_buffer).map_err(|_| error("__wrap_subinvoke: invalid method"))?;
+    let Some(host) = data.host.clone() else {
+        return Err(error("__wrap_subinvoke: no invocation in progress"));
+    };
+
+    let result = (host.subinvoker)(&uri, &method, args_buffer);
+    let succeeded = result.is_ok();
+    data.set_subinvoke_result(result);
+    Ok(succeeded as i32)
 }
 
 fn wrap_subinvoke_result_len(context: Context) -> Result<i32> {
@@ -132,7 +180,10 @@ fn wrap_subinvoke_result(mut context: Context, pointer: i32) -> Result<()> {
 
     match &data.subinvoke {
         Some(Ok(result)) => {
-            data.memory.view(&store).write(pointer as u64, result)?;
+            data.memory
+                .view(&store)
+                .write(pointer as u64, result)
+                .map_err(memory_error)?;
             Ok(())
         }
         _ => Err(error(
@@ -159,15 +210,15 @@ fn wrap_subinvoke_error(mut context: Context, pointer: i32) -> Result<()> {
         Some(Err(e)) => {
             data.memory
                 .view(&store)
-                .write(pointer as u64, e.as_bytes())?;
+                .write(pointer as u64, e.as_bytes())
+                .map_err(memory_error)?;
             Ok(())
         }
-        _ => {
-            return Err(error("wrap_subinvoke_error: No subinvoke error available"));
-        }
+        _ => Err(error("wrap_subinvoke_error: No subinvoke error available")),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn wrap_subinvoke_implementation(
     mut context: Context,
     interface_ptr: i32,
@@ -227,7 +278,10 @@ fn wrap_subinvoke_implementation_result(mut context: Context, pointer: i32) -> R
 
     match &data.subinvoke_implementation {
         Some(Ok(result)) => {
-            data.memory.view(&store).write(pointer as u64, result)?;
+            data.memory
+                .view(&store)
+                .write(pointer as u64, result)
+                .map_err(memory_error)?;
             Ok(())
         }
         _ => Err(error(
@@ -254,7 +308,8 @@ fn wrap_subinvoke_implementation_error(mut context: Context, pointer: i32) -> Re
         Some(Err(error)) => {
             data.memory
                 .view(&store)
-                .write(pointer as u64, error.as_bytes())?;
+                .write(pointer as u64, error.as_bytes())
+                .map_err(memory_error)?;
             Ok(())
         }
         _ => Err(error(
@@ -289,7 +344,10 @@ fn wrap_get_implementations_result(mut context: Context, pointer: i32) -> Result
 
     match &data.get_implementations_result {
         Some(result) => {
-            data.memory.view(&store).write(pointer as u64, result)?;
+            data.memory
+                .view(&store)
+                .write(pointer as u64, result)
+                .map_err(memory_error)?;
             Ok(())
         }
         None => Err(error(
@@ -301,7 +359,10 @@ fn wrap_get_implementations_result(mut context: Context, pointer: i32) -> Result
 fn wrap_load_env(mut context: Context, pointer: i32) -> Result<()> {
     let (data, store) = context.data_and_store_mut();
 
-    data.memory.view(&store).write(pointer as u64, &data.env)?;
+    data.memory
+        .view(&store)
+        .write(pointer as u64, &data.env)
+        .map_err(memory_error)?;
     Ok(())
 }
 
@@ -310,7 +371,11 @@ fn wrap_debug_log(mut context: Context, msg_offset: i32, msg_length: i32) -> Res
     let memory_view = data.memory.view(&store);
 
     let msg = string_from_memory(&memory_view, msg_length, msg_offset, "wrap_debug_log")?;
-    println!("Debug log: {}", msg);
+    let method = String::from_utf8_lossy(&data.method);
+    if let Some(host) = &data.host {
+        tracing::debug!(target: "wrap_debug_log", uri = %host.uri, %method, "{}", msg);
+        host.debug_log_handler.log(&host.uri, &method, &msg);
+    }
 
     Ok(())
 }
@@ -323,6 +388,12 @@ fn error(msg: &str) -> wasmer::RuntimeError {
     wasmer::RuntimeError::new(msg)
 }
 
+/// Keeps the memory error downcastable, so the instance can report out of bounds accesses as `MemoryTooSmall`
+#[inline]
+fn memory_error(error: wasmer::MemoryAccessError) -> wasmer::RuntimeError {
+    wasmer::RuntimeError::user(Box::new(error))
+}
+
 fn string_from_memory(
     memory_view: &wasmer::MemoryView<'_>,
     length: i32,
@@ -330,14 +401,12 @@ fn string_from_memory(
     import_name: &str,
 ) -> Result<String> {
     let mut buffer = empty_buffer(length);
-    memory_view.read(offset as u64, &mut buffer)?;
+    memory_view
+        .read(offset as u64, &mut buffer)
+        .map_err(memory_error)?;
     String::from_utf8(buffer).map_err(|_| error(&format!("{}: invalid string", import_name)))
 }
 
 fn empty_buffer(size: i32) -> Vec<u8> {
-    let mut empty: Vec<u8> = Vec::with_capacity(size as usize);
-    unsafe {
-        empty.set_len(size as usize);
-    }
-    empty
+    vec![0; size as usize]
 }
@@ -1,20 +1,29 @@
-use crate::{ExecutionContext, InvokeError, LoadedWrap};
-use std::time::Instant;
+use crate::{ExecutionContext, InstanceMetrics, InvokeError, LoadedWrap};
+use polywrap_uri::Uri;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Instant,
+};
 
 mod imports;
 mod state;
-pub use state::State;
+pub use state::{Host, State, Subinvoker};
 
 pub struct WrapInstance {
     last_used: Instant,
     // TODO: hold all the wasmer instance stuff
     store: wasmer::Store,
     env: wasmer::FunctionEnv<State>,
+    memory: wasmer::Memory,
     invoke: wasmer::TypedFunction<(i32, i32, i32), i32>,
+    metrics: Arc<InstanceMetrics>,
 }
 
 impl WrapInstance {
     pub fn new(loaded_wrap: &LoadedWrap) -> Self {
+        let _span = tracing::debug_span!("instantiate_module").entered();
+        let started = Instant::now();
+
         // Create a Store.
         let mut store = wasmer::Store::default();
 
@@ -24,7 +33,7 @@ impl WrapInstance {
 
         let state = State::new(memory.clone());
         let env = wasmer::FunctionEnv::new(&mut store, state);
-        let imports = imports::create(memory, &mut store, &env);
+        let imports = imports::create(memory.clone(), &mut store, &env);
 
         let instance = wasmer::Instance::new(&mut store, &loaded_wrap.module, &imports)
             .expect("wasm instantiation failed");
@@ -34,40 +43,102 @@ impl WrapInstance {
             .get_typed_function(&store, "_wrap_invoke")
             .expect("wasm invoke function not found");
 
+        let metrics = loaded_wrap.instance_metrics.clone();
+        metrics.live.fetch_add(1, Ordering::Relaxed);
+        metrics.creation.lock().unwrap().observe(started.elapsed());
+
         Self {
             last_used: Instant::now(),
             store,
             env,
+            memory,
             invoke,
+            metrics,
         }
     }
 
     pub async fn invoke(
         &mut self,
+        uri: &Uri,
         method: &str,
         args: Vec<u8>,
-        _execution_context: &ExecutionContext,
+        env: Vec<u8>,
+        execution_context: &ExecutionContext,
+        host: Host,
     ) -> Result<Vec<u8>, InvokeError> {
         let len = args.len();
+        let env_len = env.len();
         self.last_used = Instant::now();
         self.env
             .as_mut(&mut self.store)
-            .init(method.as_bytes().to_vec(), args);
-
-        match self.invoke.call(
-            &mut self.store,
-            method.len() as _,
-            len as _,
-            0, // env.len()
-        ) {
+            .init(method.as_bytes().to_vec(), args, env, host);
+
+        let uri = uri.clone();
+        let method = method.to_string();
+
+        match self
+            .invoke
+            .call(&mut self.store, method.len() as _, len as _, env_len as _)
+        {
             Ok(_) => match self.env.as_mut(&mut self.store).invoke.take() {
                 Some(Ok(result)) => Ok(result),
-                Some(Err(error)) => Err(InvokeError::from_runtime_error(error)),
-                None => Err(InvokeError::from_runtime_error(
-                    "invoke function did not return a result".to_string(),
-                )),
+                Some(Err(message)) => Err(InvokeError::from_wrap_message(uri, method, message)),
+                None => Err(InvokeError::CallFailed {
+                    uri,
+                    method,
+                    source: wasmer::RuntimeError::new("invoke function did not return a result"),
+                    backtrace: vec![],
+                }),
             },
-            Err(e) => Err(InvokeError::RuntimeError(e)),
+            Err(source) => {
+                let backtrace = execution_context.debug_info.symbolicate(source.trace());
+
+                if source.is::<imports::Abort>() {
+                    let abort = source.downcast::<imports::Abort>().unwrap();
+
+                    // Wraps typically abort when a subinvocation fails, keep the stack in that case
+                    if let Some(source) = InvokeError::decode(&abort.message) {
+                        return Err(InvokeError::Subinvocation {
+                            uri,
+                            method,
+                            source: Box::new(source),
+                        });
+                    }
+
+                    return Err(InvokeError::WrapAbort {
+                        uri,
+                        method,
+                        message: abort.message,
+                        file: abort.file,
+                        line: abort.line,
+                        column: abort.column,
+                        backtrace,
+                    });
+                }
+
+                match source.downcast_ref::<wasmer::MemoryAccessError>() {
+                    Some(wasmer::MemoryAccessError::HeapOutOfBounds) => {
+                        Err(InvokeError::MemoryTooSmall {
+                            uri,
+                            method,
+                            memory_size: self.memory.view(&self.store).data_size(),
+                            backtrace,
+                        })
+                    }
+                    _ => Err(InvokeError::CallFailed {
+                        uri,
+                        method,
+                        source,
+                        backtrace,
+                    }),
+                }
+            }
         }
     }
 }
+
+impl Drop for WrapInstance {
+    fn drop(&mut self) {
+        self.metrics.live.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -1,12 +1,29 @@
+use crate::{DebugLogHandler, InvokeError};
+use polywrap_uri::Uri;
+use std::sync::Arc;
+
 type InvokeState = Option<Result<Vec<u8>, String>>;
 
+/// Invokes another wrap on behalf of the wrap being invoked.
+pub type Subinvoker =
+    Arc<dyn Fn(&Uri, &str, Vec<u8>) -> Result<Vec<u8>, InvokeError> + Send + Sync>;
+
+/// What the wrap being invoked calls back into on the host.
+#[derive(Clone)]
+pub struct Host {
+    /// Uri the invoked wrap was resolved to, which picked the debug log handler
+    pub uri: Uri,
+    pub debug_log_handler: Arc<dyn DebugLogHandler>,
+    pub subinvoker: Subinvoker,
+}
+
 pub struct State {
+    pub host: Option<Host>,
     pub method: Vec<u8>,
     pub args: Vec<u8>,
     pub env: Vec<u8>,
     pub invoke: InvokeState,
     pub subinvoke: InvokeState,
-    // pub invoker: Arc<dyn Invoker>,
     pub get_implementations_result: Option<Vec<u8>>,
     pub subinvoke_implementation: InvokeState,
     pub memory: wasmer::Memory,
@@ -15,6 +32,7 @@ pub struct State {
 impl State {
     pub fn new(memory: wasmer::Memory) -> Self {
         Self {
+            host: None,
             method: Vec::new(),
             args: Vec::new(),
             env: Vec::new(),
@@ -26,12 +44,19 @@ impl State {
         }
     }
 
-    pub fn init(&mut self, method: Vec<u8>, args: Vec<u8>) {
+    pub fn init(&mut self, method: Vec<u8>, args: Vec<u8>, env: Vec<u8>, host: Host) {
+        self.host = Some(host);
         self.method = method;
         self.args = args;
+        self.env = env;
         self.invoke = None;
         self.subinvoke = None;
         self.get_implementations_result = None;
         self.subinvoke_implementation = None;
     }
+
+    /// Errors are encoded so the subinvocation stack survives the trip through the wrap.
+    pub fn set_subinvoke_result(&mut self, result: Result<Vec<u8>, InvokeError>) {
+        self.subinvoke = Some(result.map_err(|error| error.encode()));
+    }
 }
@@ -1,7 +1,15 @@
-use super::WrapInstance;
-use crate::LoadError;
+use super::{
+    archive::{read_archive, MAX_ARCHIVE_SIZE},
+    Abi, DebugInfo, WrapInstance,
+};
+use crate::{InstanceMetrics, LoadError, VerifyingKey, WrapHashes};
 use polywrap_uri::Uri;
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
 use tokio::{fs, sync::Mutex};
 
 /// This struct contains all the information needed to execute a wasm module (besides the instance itself).
@@ -9,26 +17,71 @@ pub struct ExecutionContext {
     /// Manifest must declare all uris it wants to use. It can't use something not in the manifest.
     /// These uris map directly to a pre-loaded wrap uri, and in theory can be configured by user.
     pub subinvoke_uri_resolution: HashMap<Uri, Uri>,
+    /// Used to resolve trap frames to function names and source locations.
+    pub debug_info: DebugInfo,
 }
 
 pub struct LoadedWrap {
+    /// Raw msgpack encoded `wrap.info`
+    pub manifest: Vec<u8>,
+    /// Parsed from `manifest`, `None` if it isn't a manifest we understand
+    pub abi: Option<Abi>,
+    /// Content hashes of `wrap.wasm` and `wrap.info`, for pinning in a lockfile
+    pub hashes: WrapHashes,
+    /// Detached ed25519 signature over `hashes`, read from `wrap.sig`
+    pub signature: Option<Vec<u8>>,
+    /// Set once the signature has been verified against a trusted key
+    pub(crate) signer: OnceLock<VerifyingKey>,
     pub execution_context: Arc<ExecutionContext>,
     pub store: wasmer::Store,
     pub module: wasmer::Module,
     pub cached_instances: Mutex<Vec<WrapInstance>>,
+    pub instance_metrics: Arc<InstanceMetrics>,
 }
 
 impl LoadedWrap {
+    /// Loads a wrap from a directory containing `wrap.wasm` and `wrap.info`, or from a single archive file
+    /// (tar, gzipped tar or zip) containing them.
     pub async fn new_from_file(path: PathBuf) -> Result<Self, LoadError> {
-        let path = path.join("wrap.wasm");
+        let metadata = fs::metadata(&path)
+            .await
+            .map_err(|_| LoadError::WrapNotFound(path.clone()))?;
+        if metadata.is_file() {
+            if metadata.len() > MAX_ARCHIVE_SIZE {
+                return Err(LoadError::InvalidArchive(
+                    "archive is too large".to_string(),
+                ));
+            }
+            let bytes = fs::read(&path)
+                .await
+                .map_err(|_| LoadError::WrapNotFound(path))?;
+            let contents = read_archive(&bytes)?;
+            return Ok(Self::new_from_bytes(&contents.wasm, contents.manifest)?
+                .with_signature(contents.signature));
+        }
+
+        let wasm_path = path.join("wrap.wasm");
+        let manifest_path = path.join("wrap.info");
+        let signature_path = path.join("wrap.sig");
 
-        let bytes = fs::read(&path)
+        let bytes = fs::read(&wasm_path)
+            .await
+            .map_err(|_| LoadError::WrapNotFound(wasm_path))?;
+        let manifest = fs::read(&manifest_path)
             .await
-            .map_err(|_| LoadError::WrapNotFound(path))?;
-        Self::new_from_bytes(&bytes)
+            .map_err(|_| LoadError::WrapNotFound(manifest_path))?;
+        // Only a missing signature means the wrap is unsigned
+        let signature = match fs::read(&signature_path).await {
+            Ok(signature) => Some(signature),
+            Err(error) if error.kind() == ErrorKind::NotFound => None,
+            Err(error) => return Err(LoadError::ReadFailed(signature_path, error)),
+        };
+        Ok(Self::new_from_bytes(&bytes, manifest)?.with_signature(signature))
     }
 
-    pub fn new_from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+    pub fn new_from_bytes(bytes: &[u8], manifest: Vec<u8>) -> Result<Self, LoadError> {
+        let _span = tracing::debug_span!("compile_module", wasm_size = bytes.len()).entered();
+
         // Create a Store.
         let store = wasmer::Store::default();
 
@@ -37,12 +90,68 @@ impl LoadedWrap {
         let module = wasmer::Module::new(&store, bytes).map_err(LoadError::InvalidWasm)?;
 
         Ok(Self {
+            hashes: WrapHashes::compute(bytes, &manifest),
+            abi: Abi::from_manifest(&manifest),
+            manifest,
+            signature: None,
+            signer: OnceLock::new(),
             execution_context: Arc::new(ExecutionContext {
                 subinvoke_uri_resolution: HashMap::new(),
+                debug_info: DebugInfo::new(bytes),
             }),
             store,
             module,
             cached_instances: Mutex::new(vec![]),
+            instance_metrics: Arc::new(InstanceMetrics::default()),
         })
     }
+
+    pub fn with_signature(mut self, signature: Option<Vec<u8>>) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// The trusted key that signed this wrap, if its signature has been verified.
+    pub fn signer(&self) -> Option<&VerifyingKey> {
+        self.signer.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("loaded-wrap-{}-{}", test, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["wrap.wasm", "wrap.info"] {
+            std::fs::copy(format!("assets/test-wrap/{}", file), dir.join(file)).unwrap();
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn missing_signature_is_unsigned() {
+        let dir = wrap_dir("unsigned");
+
+        let loaded_wrap = LoadedWrap::new_from_file(dir.clone()).await.unwrap();
+        assert!(loaded_wrap.signature.is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn unreadable_signature_is_an_error() {
+        let dir = wrap_dir("unreadable");
+        std::fs::create_dir(dir.join("wrap.sig")).unwrap();
+
+        let result = LoadedWrap::new_from_file(dir.clone()).await;
+        match result {
+            Err(LoadError::ReadFailed(path, _)) => assert_eq!(path, dir.join("wrap.sig")),
+            _ => panic!("expected the signature read to fail"),
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -1,11 +1,29 @@
+pub use polywrap_client_hmny_abi::*;
+mod archive;
+mod backtrace;
+pub use backtrace::*;
 mod closure;
 pub use closure::*;
+mod embedded;
+pub use embedded::*;
 mod instance;
 pub use instance::*;
 mod loaded;
 pub use loaded::*;
 
+// Wraps are always held behind an `Arc`, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Wrap {
     Loaded(LoadedWrap),
     Closure(ClosureWrap),
 }
+
+impl Wrap {
+    /// The wrap's ABI, if its manifest could be parsed or a closure wrap was given one.
+    pub fn abi(&self) -> Option<&Abi> {
+        match self {
+            Wrap::Loaded(loaded_wrap) => loaded_wrap.abi.as_ref(),
+            Wrap::Closure(closure_wrap) => closure_wrap.abi.as_ref(),
+        }
+    }
+}
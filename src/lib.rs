@@ -1,5 +1,89 @@
+pub use polywrap_client_hmny_macros::embed_wrap;
+/// Turns an impl block into a plugin wrap.
+///
+/// A plugin must export at least one method, so forgetting `&self` doesn't go unnoticed:
+///
+/// ```compile_fail
+/// use polywrap_client_hmny::plugin;
+///
+/// struct EmptyPlugin;
+///
+/// #[plugin]
+/// impl EmptyPlugin {
+///     fn helper() {}
+/// }
+/// ```
+///
+/// `abi()` and `manifest()` are generated for the plugin, so its own functions can't take these names:
+///
+/// ```compile_fail
+/// use polywrap_client_hmny::plugin;
+///
+/// struct AbiPlugin;
+///
+/// #[plugin]
+/// impl AbiPlugin {
+///     fn abi(&self) {}
+/// }
+/// ```
+pub use polywrap_client_hmny_macros::plugin;
+/// Generates types and a typed facade from a wrap's `wrap.info`.
+///
+/// ```
+/// use polywrap_client_hmny::{uri, wrap_bindings, AbiObject, ClientBuilder, Uri};
+/// use std::path::Path;
+///
+/// wrap_bindings!("assets/test-wrap", TestWrap);
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let client = ClientBuilder::new()
+///     .add_file(uri!("hmny-wrap/test-wrap"), Path::new("assets/test-wrap"))
+///     .load()
+///     .await
+///     .unwrap();
+///
+/// let result: SampleResult = TestWrap::new(client, uri!("hmny-wrap/test-wrap"))
+///     .sample_method(ArgsSampleMethod { arg: "a".to_string() })
+///     .await
+///     .unwrap();
+/// assert_eq!(result.result, "a from sample_method");
+///
+/// // The generated types describe themselves the way the wrap's ABI does
+/// let mut abi = polywrap_client_hmny::Abi::default();
+/// let properties = ArgsSampleMethod::properties(&mut abi);
+/// assert_eq!(properties[0].name, "arg");
+/// assert_eq!(properties[0].ty.name(), "String!");
+/// # }
+/// ```
+///
+/// Object types that refer to themselves through an optional field are boxed:
+///
+/// ```
+/// use polywrap_client_hmny::{wrap_bindings, AbiObject};
+///
+/// // `type Node { value: Int!, next: Node }`
+/// wrap_bindings!("assets/linked-list", LinkedList);
+///
+/// let list = Node {
+///     value: 1,
+///     next: Some(Box::new(Node { value: 2, next: None })),
+/// };
+/// assert_eq!(list.next.unwrap().value, 2);
+///
+/// let mut abi = polywrap_client_hmny::Abi::default();
+/// assert_eq!(Node::properties(&mut abi)[1].ty.name(), "Node");
+/// ```
+pub use polywrap_client_hmny_macros::wrap_bindings;
 pub use polywrap_core_macros::uri;
 pub use polywrap_uri::Uri;
 
 mod client;
 pub use client::*;
+
+/// Used by code generated by the macros, not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use polywrap_msgpack_serde as msgpack;
+    pub use serde;
+}
@@ -1,19 +1,25 @@
-use polywrap_client_hmny::{uri, Client, ClientBuilder, ClosureWrap, Uri};
-use serde::{Deserialize, Serialize};
+use polywrap_client_hmny::{plugin, uri, wrap_bindings, Client, ClientBuilder, Uri};
 use std::path::Path;
 
+wrap_bindings!("assets/test-wrap", TestWrap);
+
+/// Implements the test wrap on the host.
+struct TestPlugin;
+
+#[plugin]
+impl TestPlugin {
+    fn sample_method(&self, args: ArgsSampleMethod) -> Result<SampleResult, String> {
+        Ok(SampleResult {
+            result: format!("{} from hmny-core/test-wrap", args.arg),
+        })
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let client = ClientBuilder::new()
         .add_file(uri!("hmny-wrap/test-wrap"), Path::new("./assets/test-wrap"))
-        .add_closure(
-            uri!("hmny-core/test-wrap"),
-            ClosureWrap::new().add_method("sampleMethod", |args: &ArgsSampleMethod| {
-                Ok(SampleResult {
-                    result: format!("{} from hmny-core/test-wrap", args.arg),
-                })
-            }),
-        )
+        .add_closure(uri!("hmny-core/test-wrap"), TestPlugin.into())
         .load()
         .await
         .expect("failed to load wraps");
@@ -36,25 +42,11 @@ async fn main() {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct ArgsSampleMethod {
-    pub arg: String,
-}
-
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct SampleResult {
-    pub result: String,
-}
-
 async fn invoke_wasm(client: Client, uri: Uri, desc: &str) {
-    let result: SampleResult = client
-        .invoke(
-            &uri,
-            "sampleMethod",
-            ArgsSampleMethod {
-                arg: format!("{} from sample_method", desc),
-            },
-        )
+    let result = TestWrap::new(client, uri)
+        .sample_method(ArgsSampleMethod {
+            arg: format!("{} from sample_method", desc),
+        })
         .await
         .unwrap();
 
@@ -0,0 +1,41 @@
+use polywrap_client_hmny::{
+    embed_wrap, sign_wrap, uri, ClientBuilder, EmbeddedWrap, LoadError, SigningKey, Uri, WrapHashes,
+};
+
+const TEST_WRAP: EmbeddedWrap = embed_wrap!("assets/test-wrap");
+
+fn signed(key: &SigningKey) -> EmbeddedWrap {
+    let hashes = WrapHashes::compute(TEST_WRAP.wasm, TEST_WRAP.manifest);
+    EmbeddedWrap {
+        signature: Some(sign_wrap(key, &hashes).leak()),
+        ..TEST_WRAP
+    }
+}
+
+async fn load(embedded_wrap: EmbeddedWrap, trusted: &SigningKey) -> Result<(), LoadError> {
+    ClientBuilder::new()
+        .add_embedded(uri!("hmny-wrap/test-wrap"), embedded_wrap)
+        .add_trusted_key(trusted.verifying_key())
+        .require_signatures()
+        .load()
+        .await
+        .map(|_| ())
+}
+
+#[tokio::test]
+async fn unsigned_wrap_has_no_signature() {
+    assert!(TEST_WRAP.signature.is_none());
+
+    let result = load(TEST_WRAP, &SigningKey::from_bytes(&[1; 32])).await;
+    assert!(matches!(result, Err(LoadError::Unsigned(_))));
+}
+
+#[tokio::test]
+async fn signature_is_passed_through() {
+    let publisher = SigningKey::from_bytes(&[1; 32]);
+
+    load(signed(&publisher), &publisher).await.unwrap();
+
+    let result = load(signed(&publisher), &SigningKey::from_bytes(&[2; 32])).await;
+    assert!(matches!(result, Err(LoadError::InvalidSignature(_))));
+}
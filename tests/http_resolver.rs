@@ -0,0 +1,178 @@
+use polywrap_client_hmny::{HttpResolver, LoadError, Uri, UriResolution, UriResolver};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Serves `files` by path over HTTP/1.1 with their status, recording every requested path.
+struct Server {
+    port: u16,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Server {
+    async fn start(files: HashMap<String, (u16, Vec<u8>)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                let (status, body) = files.get(&path).cloned().unwrap_or((404, vec![]));
+                let mut response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&body);
+                recorded.lock().unwrap().push(path);
+                // The client may hang up before reading the whole response
+                stream.write_all(&response).await.ok();
+                stream.shutdown().await.ok();
+            }
+        });
+
+        Self { port, requests }
+    }
+
+    fn uri(&self, path: &str) -> Uri {
+        Uri::try_from(format!("wrap://http/127.0.0.1:{}/{}", self.port, path)).unwrap()
+    }
+
+    fn take_requests(&self) -> Vec<String> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
+
+fn test_wrap_files() -> HashMap<String, (u16, Vec<u8>)> {
+    let mut files = HashMap::new();
+    for file in ["wrap.wasm", "wrap.info"] {
+        let bytes = std::fs::read(format!("assets/test-wrap/{}", file)).unwrap();
+        files.insert(format!("/test-wrap/{}", file), (200, bytes));
+    }
+    files
+}
+
+async fn test_wrap_server() -> Server {
+    Server::start(test_wrap_files()).await
+}
+
+fn cache_dir(name: &str) -> PathBuf {
+    let cache_dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&cache_dir).ok();
+    cache_dir
+}
+
+#[tokio::test]
+async fn fetches_wrap() {
+    let server = test_wrap_server().await;
+
+    let resolution = HttpResolver::new()
+        .resolve(&server.uri("test-wrap"))
+        .await
+        .unwrap();
+
+    assert!(matches!(resolution, UriResolution::Wrap(_)));
+    assert_eq!(
+        server.take_requests(),
+        [
+            "/test-wrap/wrap.wasm",
+            "/test-wrap/wrap.info",
+            "/test-wrap/wrap.sig"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn reuses_disk_cache_across_resolvers() {
+    let server = test_wrap_server().await;
+    let cache_dir = cache_dir("http-resolver-disk-cache");
+
+    let first = HttpResolver::new().with_cache_dir(&cache_dir);
+    first.resolve(&server.uri("test-wrap")).await.unwrap();
+    assert_eq!(server.take_requests().len(), 3);
+
+    let second = HttpResolver::new().with_cache_dir(&cache_dir);
+    let resolution = second.resolve(&server.uri("test-wrap")).await.unwrap();
+
+    // Only the missing signature is asked for again
+    assert!(matches!(resolution, UriResolution::Wrap(_)));
+    assert_eq!(server.take_requests(), ["/test-wrap/wrap.sig"]);
+
+    let wrap_dir = cache_dir
+        .join("http")
+        .join(format!("127.0.0.1_{}", server.port))
+        .join("test-wrap");
+    let mut cached: Vec<String> = std::fs::read_dir(wrap_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    cached.sort();
+    assert_eq!(cached, ["wrap.info", "wrap.wasm"]);
+
+    std::fs::remove_dir_all(&cache_dir).ok();
+}
+
+#[tokio::test]
+async fn missing_wrap_is_http_error() {
+    let server = test_wrap_server().await;
+
+    let result = HttpResolver::new().resolve(&server.uri("missing")).await;
+
+    match result {
+        Err(LoadError::Http(error)) => assert_eq!(error.status().map(|s| s.as_u16()), Some(404)),
+        _ => panic!("expected a 404"),
+    }
+    assert_eq!(server.take_requests(), ["/missing/wrap.wasm"]);
+}
+
+#[tokio::test]
+async fn failing_signature_fetch_is_not_unsigned() {
+    let mut files = test_wrap_files();
+    files.insert("/test-wrap/wrap.sig".to_string(), (500, vec![]));
+    let server = Server::start(files).await;
+
+    let result = HttpResolver::new().resolve(&server.uri("test-wrap")).await;
+
+    match result {
+        Err(LoadError::Http(error)) => assert_eq!(error.status().map(|s| s.as_u16()), Some(500)),
+        _ => panic!("expected the signature fetch to fail"),
+    }
+}
+
+#[tokio::test]
+async fn oversized_response_is_rejected() {
+    let mut files = test_wrap_files();
+    files.insert(
+        "/test-wrap/wrap.wasm".to_string(),
+        (200, vec![0; 64 * 1024 * 1024 + 1]),
+    );
+    let server = Server::start(files).await;
+
+    let result = HttpResolver::new().resolve(&server.uri("test-wrap")).await;
+
+    match result {
+        Err(LoadError::ResponseTooLarge(url)) => assert!(url.ends_with("/test-wrap/wrap.wasm")),
+        _ => panic!("expected the response to be too large"),
+    }
+}
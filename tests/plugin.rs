@@ -0,0 +1,49 @@
+use polywrap_client_hmny::{plugin, uri, wrap_bindings, ClientBuilder, InvokeError, Uri};
+
+wrap_bindings!("assets/test-wrap", TestWrap);
+
+struct TestPlugin;
+
+#[plugin(name = "test-plugin")]
+impl TestPlugin {
+    fn sample_method(&self, args: ArgsSampleMethod) -> SampleResult {
+        SampleResult {
+            result: format!("{}{}", args.arg, self.suffix()),
+        }
+    }
+
+    #[plugin(skip)]
+    fn suffix(&self) -> String {
+        " from the plugin".to_string()
+    }
+}
+
+#[tokio::test]
+async fn skipped_methods_are_not_exported() {
+    let methods: Vec<String> = TestPlugin::abi()
+        .methods
+        .into_iter()
+        .map(|method| method.name)
+        .collect();
+    assert_eq!(methods, ["sampleMethod"]);
+
+    let client = ClientBuilder::new()
+        .add_closure(uri!("hmny-core/test-plugin"), TestPlugin.into())
+        .load()
+        .await
+        .unwrap();
+    let test_plugin = TestWrap::new(client.clone(), uri!("hmny-core/test-plugin"));
+
+    let result = test_plugin
+        .sample_method(ArgsSampleMethod {
+            arg: "a".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(result.result, "a from the plugin");
+
+    let result = client
+        .invoke::<(), String>(&uri!("hmny-core/test-plugin"), "suffix", ())
+        .await;
+    assert!(matches!(result, Err(InvokeError::MethodNotFound { .. })));
+}